@@ -0,0 +1,35 @@
+use arduino_hal::{
+    hal::port::PC2,
+    port::{mode::Analog, Pin},
+    Adc,
+};
+use ox_bot::hal::BatterySensor;
+
+// Voltage divider from the battery to A2: 10k to the pack, 10k to ground,
+// so the 8.7V of a fully charged pack reads 4.35V
+const DIVIDER_TOP_OHMS: u32 = 10_000;
+const DIVIDER_BOTTOM_OHMS: u32 = 10_000;
+// ADC reference (AVcc) and full scale reading
+const ADC_REFERENCE_MV: u32 = 5000;
+const ADC_MAX: u32 = 1023;
+
+/// Battery pack voltage read through a divider on A2.
+pub struct BatteryDivider {
+    adc: Adc,
+    a2: Pin<Analog, PC2>,
+}
+
+impl BatteryDivider {
+    /// Creates the sensor from the ADC and the A2 pin already set as analog input.
+    pub fn new(adc: Adc, a2: Pin<Analog, PC2>) -> Self {
+        Self { adc, a2 }
+    }
+}
+
+impl BatterySensor for BatteryDivider {
+    fn read_millivolts(&mut self) -> u16 {
+        let raw = self.a2.analog_read(&mut self.adc) as u32;
+        let pin_mv = raw * ADC_REFERENCE_MV / ADC_MAX;
+        (pin_mv * (DIVIDER_TOP_OHMS + DIVIDER_BOTTOM_OHMS) / DIVIDER_BOTTOM_OHMS) as u16
+    }
+}
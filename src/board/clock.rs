@@ -0,0 +1,145 @@
+use arduino_hal::Peripherals;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use core::cell::Cell;
+use ox_bot::hal::Clock;
+
+const MICROSECONDS_PER_TICK: u32 = 4;
+const TICKS_PER_SECOND: u32 = 1_000_000 / MICROSECONDS_PER_TICK;
+const MICROSECONDS_PER_OVERFLOW: u32 = 65536 * MICROSECONDS_PER_TICK;
+const MILLISECONDS_PER_OVERFLOW: u32 = MICROSECONDS_PER_OVERFLOW / 1000;
+const FRACTION_PER_OVERFLOW: u16 = (MICROSECONDS_PER_OVERFLOW % 1000) as u16;
+
+static MICROS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static MILLIS: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+static MILLIS_FRACTION: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+static CONTROL_PERIOD_TICKS: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+static PENDING_CONTROL_TICKS: Mutex<Cell<u16>> = Mutex::new(Cell::new(0));
+
+/// Clock of the robot, backed by Timer/Counter1.
+pub struct Timer1Clock {}
+
+impl Clock for Timer1Clock {
+    fn millis(&self) -> u32 {
+        millis()
+    }
+
+    fn micros(&self) -> u32 {
+        micros()
+    }
+
+    fn wait_for_tick(&self) -> u16 {
+        loop {
+            let pending =
+                avr_device::interrupt::free(|cs| PENDING_CONTROL_TICKS.borrow(cs).replace(0));
+            if pending > 0 {
+                return pending;
+            }
+        }
+    }
+
+    /// Moves compare unit A a period from now, at least 4 Hz so that a period fits
+    /// in the 16 bit timer, and enables its interrupt.
+    fn set_control_rate(&self, rate_hz: u16) {
+        let control_period_ticks = (TICKS_PER_SECOND / rate_hz.max(4) as u32) as u16;
+        let tc1 = unsafe { &*avr_device::atmega328p::TC1::ptr() };
+        avr_device::interrupt::free(|cs| {
+            CONTROL_PERIOD_TICKS.borrow(cs).set(control_period_ticks);
+            let next_compare = tc1.tcnt1.read().bits().wrapping_add(control_period_ticks);
+            tc1.ocr1a.write(|w| w.bits(next_compare));
+        });
+        tc1.timsk1.modify(|_, w| w.ocie1a().set_bit());
+    }
+}
+
+/// Starts Timer/Counter1 free running (1 tick every 4 microseconds) and
+/// enables its overflow interrupt to extend it into 32 bit counters.
+/// The PPM decoder measures its pulses on the same timer.
+/// Compare unit A raises the control ticks once `set_control_rate` is called,
+/// with the rate of the loaded settings.
+pub fn init(ph: &Peripherals) -> Timer1Clock {
+    ph.TC1.tccr1b.write(|w| w.cs1().prescale_64());
+    ph.TC1.timsk1.write(|w| w.toie1().set_bit());
+    Timer1Clock {}
+}
+
+/// Returns the microseconds elapsed since `init`, wrapping every ~71 minutes.
+pub fn micros() -> u32 {
+    avr_device::interrupt::free(|cs| {
+        let (overflow, ticks) = read_timer_counter_1();
+        let mut micros = MICROS.borrow(cs).get();
+        if overflow {
+            micros = micros.wrapping_add(MICROSECONDS_PER_OVERFLOW);
+        }
+        micros.wrapping_add(ticks as u32 * MICROSECONDS_PER_TICK)
+    })
+}
+
+/// Returns the milliseconds elapsed since `init`, wrapping every ~49 days.
+pub fn millis() -> u32 {
+    avr_device::interrupt::free(|cs| {
+        let (overflow, ticks) = read_timer_counter_1();
+        let mut millis = MILLIS.borrow(cs).get();
+        let mut fraction = MILLIS_FRACTION.borrow(cs).get() as u32;
+        if overflow {
+            millis = millis.wrapping_add(MILLISECONDS_PER_OVERFLOW);
+            fraction += FRACTION_PER_OVERFLOW as u32;
+        }
+        millis.wrapping_add((fraction + ticks as u32 * MICROSECONDS_PER_TICK) / 1000)
+    })
+}
+
+/// Reads TCNT1 together with a pending overflow that the interrupt has not
+/// handled yet. Must be called with interrupts disabled.
+fn read_timer_counter_1() -> (bool, u16) {
+    let tc1 = unsafe { &*avr_device::atmega328p::TC1::ptr() };
+    let ticks = tc1.tcnt1.read().bits();
+    let overflow = tc1.tifr1.read().tov1().bit_is_set() && ticks < 0x8000;
+    (overflow, ticks)
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn TIMER1_OVF() {
+    avr_device::interrupt::free(|cs| {
+        add_overflow(cs);
+    });
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn TIMER1_COMPA() {
+    avr_device::interrupt::free(|cs| {
+        add_control_tick(cs);
+    });
+}
+
+/// Accumulates one full Timer/Counter1 period into the clock counters.
+fn add_overflow(cs: CriticalSection) {
+    let micros = MICROS.borrow(cs);
+    micros.set(micros.get().wrapping_add(MICROSECONDS_PER_OVERFLOW));
+
+    let mut millis = MILLIS
+        .borrow(cs)
+        .get()
+        .wrapping_add(MILLISECONDS_PER_OVERFLOW);
+    let mut fraction = MILLIS_FRACTION.borrow(cs).get() + FRACTION_PER_OVERFLOW;
+    if fraction >= 1000 {
+        fraction -= 1000;
+        millis = millis.wrapping_add(1);
+    }
+    MILLIS.borrow(cs).set(millis);
+    MILLIS_FRACTION.borrow(cs).set(fraction);
+}
+
+/// Counts a control tick and schedules the next one a period later.
+/// The timer keeps running freely, only the compare value moves.
+fn add_control_tick(cs: CriticalSection) {
+    let pending = PENDING_CONTROL_TICKS.borrow(cs);
+    pending.set(pending.get().saturating_add(1));
+
+    let tc1 = unsafe { &*avr_device::atmega328p::TC1::ptr() };
+    let next_compare = tc1
+        .ocr1a
+        .read()
+        .bits()
+        .wrapping_add(CONTROL_PERIOD_TICKS.borrow(cs).get());
+    tc1.ocr1a.write(|w| w.bits(next_compare));
+}
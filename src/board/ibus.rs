@@ -0,0 +1,54 @@
+use crate::board::clock;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use core::cell::{Cell, RefCell};
+use ox_bot::{
+    hal::{FrameStats, RcChannels, RcFrame, RcInput, MAX_RC_CHANNELS},
+    robot::ibus::IbusDecoder,
+};
+
+static DECODER: Mutex<RefCell<IbusDecoder>> = Mutex::new(RefCell::new(IbusDecoder::new()));
+static FRAME: Mutex<Cell<Option<RcFrame>>> = Mutex::new(Cell::new(None));
+
+pub struct Ibus {}
+
+impl Ibus {
+    /// Initializes iBUS reading from the serial RX pin (D0).
+    /// The serial port must run at 115200 baud with the RX complete interrupt enabled.
+    pub fn init() -> Self {
+        unsafe {
+            avr_device::interrupt::enable();
+        }
+        Self {}
+    }
+}
+
+impl RcInput for Ibus {
+    /// Returns the first 10 channels of the last valid frame, if any.
+    fn get_frame(&self) -> Option<RcFrame> {
+        avr_device::interrupt::free(|cs| FRAME.borrow(cs).get())
+    }
+
+    /// Returns the counts of good and bad frames since start.
+    fn get_frame_stats(&self) -> FrameStats {
+        avr_device::interrupt::free(|cs| DECODER.borrow(cs).borrow().get_stats())
+    }
+}
+
+/// Feeds a byte received on RX to the decoder and publishes the frame it
+/// completes, if any. Called from the USART RX interrupt.
+pub fn process_byte(cs: CriticalSection, byte: u8) {
+    if let Some(channels) = DECODER.borrow(cs).borrow_mut().push_byte(byte) {
+        let mut values = [0; MAX_RC_CHANNELS];
+        values.copy_from_slice(&channels[..MAX_RC_CHANNELS]);
+        let frame = FRAME.borrow(cs);
+        let sequence = frame.get().map_or(0, |last| last.sequence.wrapping_add(1));
+        frame.set(Some(RcFrame {
+            channels: RcChannels {
+                values,
+                count: MAX_RC_CHANNELS,
+            },
+            received_ms: clock::millis(),
+            sequence,
+        }));
+    }
+}
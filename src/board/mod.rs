@@ -0,0 +1,230 @@
+mod battery;
+mod clock;
+mod ibus;
+mod motor;
+mod ppm;
+mod serial;
+mod storage;
+
+use arduino_hal::{
+    default_serial,
+    hal::port::{PB0, PB1, PB2, PB3, PB4, PB5, PC0, PC1, PD3, PD4, PD5, PD6, PD7},
+    pac::{TC0, TC2},
+    pins,
+    port::Pin,
+    prelude::*,
+    simple_pwm::{IntoPwmPin, Timer0Pwm, Timer2Pwm},
+    Peripherals,
+};
+use battery::BatteryDivider;
+use clock::Timer1Clock;
+use ibus::Ibus;
+use motor::{HBridge, HBridgeConfig};
+use ox_bot::hal::{FrameStats, Motor, MotorId, Motors, RcFrame, RcInput, StatusLed, StopMode};
+use ppm::Ppm;
+use serial::Serial;
+use storage::EepromStorage;
+
+pub enum FlySkyPpmPin {
+    D2,
+    #[allow(dead_code)]
+    D3,
+}
+
+/// Receiver output the radio channels are read from.
+pub enum FlySkyReceiver {
+    /// PPM pulse train on an external interrupt pin.
+    Ppm(FlySkyPpmPin),
+    /// iBUS serial frames on the RX pin (D0) at 115200 baud.
+    #[allow(dead_code)]
+    Ibus,
+}
+
+pub enum FlySkyInput {
+    Ppm(Ppm),
+    Ibus(Ibus),
+}
+
+impl FlySkyInput {
+    /// Initializes reading of the given receiver output.
+    fn init(peripherals: &Peripherals, receiver: FlySkyReceiver) -> Self {
+        match receiver {
+            FlySkyReceiver::Ppm(FlySkyPpmPin::D2) => {
+                FlySkyInput::Ppm(Ppm::init_from_d2(peripherals))
+            }
+            FlySkyReceiver::Ppm(FlySkyPpmPin::D3) => {
+                FlySkyInput::Ppm(Ppm::init_from_d3(peripherals))
+            }
+            FlySkyReceiver::Ibus => FlySkyInput::Ibus(Ibus::init()),
+        }
+    }
+
+    /// Returns true if the channels are read from the serial port.
+    fn uses_serial(&self) -> bool {
+        matches!(self, FlySkyInput::Ibus(_))
+    }
+}
+
+impl RcInput for FlySkyInput {
+    fn get_frame(&self) -> Option<RcFrame> {
+        match self {
+            FlySkyInput::Ppm(ppm) => ppm.get_frame(),
+            FlySkyInput::Ibus(ibus) => ibus.get_frame(),
+        }
+    }
+
+    fn get_frame_stats(&self) -> FrameStats {
+        match self {
+            FlySkyInput::Ppm(ppm) => ppm.get_frame_stats(),
+            FlySkyInput::Ibus(ibus) => ibus.get_frame_stats(),
+        }
+    }
+}
+
+/// Loads and configures timer 0 for PWM.
+fn load_timer0_pwm(tc0: TC0) -> Timer0Pwm {
+    Timer0Pwm::new(tc0, arduino_hal::simple_pwm::Prescaler::Prescale64)
+}
+
+/// Loads and configures timer 2 for PWM.
+fn load_timer2_pwm(tc2: TC2) -> Timer2Pwm {
+    Timer2Pwm::new(tc2, arduino_hal::simple_pwm::Prescaler::Prescale64)
+}
+
+// Wiring of the motors to the two TB6612 drivers: PWM, IN1 and IN2. IN1 of motor D
+// was on D13 before the status LED took it.
+type MotorA = HBridge<Timer0Pwm, PD5, PD4, PD7>;
+type MotorB = HBridge<Timer0Pwm, PD6, PB0, PB4>;
+type MotorC = HBridge<Timer2Pwm, PB3, PB2, PB1>;
+type MotorD = HBridge<Timer2Pwm, PD3, PC1, PC0>;
+
+// Direction of each motor, and how it stops until the robot chooses
+const MOTOR_A: HBridgeConfig = HBridgeConfig {
+    inverted: false,
+    stop: StopMode::Brake,
+};
+const MOTOR_B: HBridgeConfig = HBridgeConfig {
+    inverted: false,
+    stop: StopMode::Brake,
+};
+const MOTOR_C: HBridgeConfig = HBridgeConfig {
+    inverted: false,
+    stop: StopMode::Brake,
+};
+const MOTOR_D: HBridgeConfig = HBridgeConfig {
+    inverted: false,
+    stop: StopMode::Brake,
+};
+
+/// Onboard LED of the Arduino Uno on D13.
+pub struct OnboardLed {
+    d13: Pin<arduino_hal::port::mode::Output, PB5>,
+}
+
+impl StatusLed for OnboardLed {
+    fn set(&mut self, on: bool) {
+        if on {
+            self.d13.set_high();
+        } else {
+            self.d13.set_low();
+        }
+    }
+}
+
+pub struct BoardMotors {
+    motor_a: MotorA,
+    motor_b: MotorB,
+    motor_c: MotorC,
+    motor_d: MotorD,
+}
+
+impl Motors for BoardMotors {
+    fn get(&mut self, id: MotorId) -> &mut dyn Motor {
+        match id {
+            MotorId::A => &mut self.motor_a,
+            MotorId::B => &mut self.motor_b,
+            MotorId::C => &mut self.motor_c,
+            MotorId::D => &mut self.motor_d,
+        }
+    }
+}
+
+/// Peripherals of the Arduino Uno used by the robot.
+pub struct Board {
+    pub motors: BoardMotors,
+    pub flysky: FlySkyInput,
+    pub clock: Timer1Clock,
+    pub serial: Serial,
+    pub led: OnboardLed,
+    pub battery: BatteryDivider,
+    pub storage: EepromStorage,
+}
+
+impl Board {
+    /// Initializes the clock, the receiver input, the serial port, the LED, the
+    /// battery sensor, the settings storage and the motors.
+    /// The robot starts the control loop ticks at the rate of its settings.
+    pub fn init(peripherals: Peripherals, baudrate: u32, receiver: FlySkyReceiver) -> Self {
+        // Start the clock used by the PPM decoder, the failsafe and the control loop
+        let clock = clock::init(&peripherals);
+        // Init PPM or iBUS protocol of flysky radio control
+        let flysky = FlySkyInput::init(&peripherals, receiver);
+        let timer0 = load_timer0_pwm(peripherals.TC0);
+        let timer2 = load_timer2_pwm(peripherals.TC2);
+        let pins = pins!(peripherals);
+        let mut serial = default_serial!(peripherals, pins, baudrate);
+        // RX (D0) receives the iBUS frames or the shell commands, TX (D1) keeps reporting
+        serial.listen(arduino_hal::hal::usart::Event::RxComplete);
+        let serial = Serial::new(serial, flysky.uses_serial());
+
+        let motor_a = MotorA::new(
+            pins.d5.into_output().into_pwm(&timer0),
+            pins.d4.into_output(),
+            pins.d7.into_output(),
+            MOTOR_A,
+        );
+        let motor_b = MotorB::new(
+            pins.d6.into_output().into_pwm(&timer0),
+            pins.d8.into_output(),
+            pins.d12.into_output(),
+            MOTOR_B,
+        );
+        let motor_c = MotorC::new(
+            pins.d11.into_output().into_pwm(&timer2),
+            pins.d10.into_output(),
+            pins.d9.into_output(),
+            MOTOR_C,
+        );
+        let motor_d = MotorD::new(
+            pins.d3.into_output().into_pwm(&timer2),
+            pins.a1.into_output(),
+            pins.a0.into_output(),
+            MOTOR_D,
+        );
+
+        let led = OnboardLed {
+            d13: pins.d13.into_output(),
+        };
+
+        let mut adc = arduino_hal::Adc::new(peripherals.ADC, Default::default());
+        let a2 = pins.a2.into_analog_input(&mut adc);
+        let battery = BatteryDivider::new(adc, a2);
+
+        let storage = EepromStorage::new(arduino_hal::Eeprom::new(peripherals.EEPROM));
+
+        Self {
+            motors: BoardMotors {
+                motor_a,
+                motor_b,
+                motor_c,
+                motor_d,
+            },
+            flysky,
+            clock,
+            serial,
+            led,
+            battery,
+            storage,
+        }
+    }
+}
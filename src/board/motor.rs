@@ -0,0 +1,91 @@
+use arduino_hal::{
+    port::{mode, Pin, PinOps},
+    simple_pwm::PwmPinOps,
+};
+use ox_bot::hal::{Motor, StopMode};
+
+/// How a motor is wired to its H-bridge.
+#[derive(Clone, Copy)]
+pub struct HBridgeConfig {
+    /// Swaps forward and backward, for a motor wired the other way around.
+    pub inverted: bool,
+    /// Stop mode until the robot chooses one.
+    pub stop: StopMode,
+}
+
+/// Motor driven by one channel of an H-bridge (TB6612): the speed on a PWM pin and
+/// the direction on the IN1 and IN2 pins.
+pub struct HBridge<TC, PWM, IN1, IN2> {
+    pwm: Pin<mode::PwmOutput<TC>, PWM>,
+    in1: Pin<mode::Output, IN1>,
+    in2: Pin<mode::Output, IN2>,
+    config: HBridgeConfig,
+}
+
+impl<TC, PWM, IN1, IN2> HBridge<TC, PWM, IN1, IN2>
+where
+    PWM: PwmPinOps<TC>,
+    IN1: PinOps,
+    IN2: PinOps,
+{
+    /// Creates the driver from pins already set as outputs, and stops the motor.
+    pub fn new(
+        pwm: Pin<mode::PwmOutput<TC>, PWM>,
+        in1: Pin<mode::Output, IN1>,
+        in2: Pin<mode::Output, IN2>,
+        config: HBridgeConfig,
+    ) -> Self {
+        let mut motor = Self {
+            pwm,
+            in1,
+            in2,
+            config,
+        };
+        motor.stop();
+        motor
+    }
+
+    /// Spins the motor at `value`, with IN2 high when `in2_high`.
+    fn spin(&mut self, value: u8, in2_high: bool) {
+        self.pwm.enable();
+        self.pwm.set_duty(value);
+        if in2_high {
+            self.in1.set_low();
+            self.in2.set_high();
+        } else {
+            self.in1.set_high();
+            self.in2.set_low();
+        }
+    }
+}
+
+impl<TC, PWM, IN1, IN2> Motor for HBridge<TC, PWM, IN1, IN2>
+where
+    PWM: PwmPinOps<TC>,
+    IN1: PinOps,
+    IN2: PinOps,
+{
+    fn forward(&mut self, value: u8) {
+        self.spin(value, !self.config.inverted);
+    }
+    fn backward(&mut self, value: u8) {
+        self.spin(value, self.config.inverted);
+    }
+    fn stop(&mut self) {
+        self.pwm.set_duty(0);
+        self.pwm.disable();
+        match self.config.stop {
+            StopMode::Brake => {
+                self.in1.set_high();
+                self.in2.set_high();
+            }
+            StopMode::Coast => {
+                self.in1.set_low();
+                self.in2.set_low();
+            }
+        }
+    }
+    fn set_stop_mode(&mut self, mode: StopMode) {
+        self.config.stop = mode;
+    }
+}
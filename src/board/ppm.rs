@@ -0,0 +1,82 @@
+use crate::board::clock;
+use arduino_hal::Peripherals;
+use avr_device::interrupt::{CriticalSection, Mutex};
+use core::cell::{Cell, RefCell};
+use ox_bot::{
+    hal::{FrameStats, RcFrame, RcInput},
+    robot::ppm::PpmDecoder,
+};
+
+// The decoder assembles each frame in its own buffer, only good frames are copied
+// to FRAME, which the main loop reads as a whole.
+static DECODER: Mutex<RefCell<PpmDecoder>> = Mutex::new(RefCell::new(PpmDecoder::new()));
+static FRAME: Mutex<Cell<Option<RcFrame>>> = Mutex::new(Cell::new(None));
+
+pub struct Ppm {}
+
+impl Ppm {
+    /// Initializes PPM reading from digital pin D2.
+    /// Timer/Counter1 must already be running, see `clock::init`.
+    pub fn init_from_d2(ph: &Peripherals) -> Self {
+        ph.EXINT.eicra.write(|w| w.isc0().bits(0b11));
+        ph.EXINT.eimsk.write(|w| w.int0().set_bit());
+        avr_interrupt_enable();
+        Self {}
+    }
+
+    /// Initializes PPM reading from digital pin D3.
+    /// Timer/Counter1 must already be running, see `clock::init`.
+    pub fn init_from_d3(ph: &Peripherals) -> Self {
+        ph.EXINT.eicra.write(|w| w.isc1().bits(0b11));
+        ph.EXINT.eimsk.write(|w| w.int1().set_bit());
+        avr_interrupt_enable();
+        Self {}
+    }
+}
+
+impl RcInput for Ppm {
+    /// Returns the last good PPM frame, if any.
+    fn get_frame(&self) -> Option<RcFrame> {
+        avr_device::interrupt::free(|cs| FRAME.borrow(cs).get())
+    }
+
+    /// Returns the counts of good, bad and missed frames since start.
+    fn get_frame_stats(&self) -> FrameStats {
+        avr_device::interrupt::free(|cs| DECODER.borrow(cs).borrow().get_stats())
+    }
+}
+
+/// Enables global AVR interrupts.
+fn avr_interrupt_enable() {
+    unsafe {
+        avr_device::interrupt::enable();
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn INT0() {
+    let now_us = clock::micros();
+    avr_device::interrupt::free(|cs| process_edge(cs, now_us));
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn INT1() {
+    let now_us = clock::micros();
+    avr_device::interrupt::free(|cs| process_edge(cs, now_us));
+}
+
+/// Feeds an edge to the decoder and publishes the good frame it completes, if any.
+/// The edge is timed on the 32 bit clock, so a silence longer than a wrap of
+/// Timer/Counter1 is not shortened.
+fn process_edge(cs: CriticalSection, now_us: u32) {
+    let mut decoder = DECODER.borrow(cs).borrow_mut();
+    if decoder.push_edge(now_us) {
+        let frame = FRAME.borrow(cs);
+        let sequence = frame.get().map_or(0, |last| last.sequence.wrapping_add(1));
+        frame.set(Some(RcFrame {
+            channels: decoder.get_channels(),
+            received_ms: clock::millis(),
+            sequence,
+        }));
+    }
+}
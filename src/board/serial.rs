@@ -0,0 +1,110 @@
+use crate::board::ibus;
+use arduino_hal::{
+    hal::port::{PD0, PD1},
+    pac::USART0,
+    port::{
+        mode::{Input, Output},
+        Pin,
+    },
+    Usart,
+};
+use avr_device::interrupt::Mutex;
+use core::{
+    cell::{Cell, RefCell},
+    convert::Infallible,
+};
+use ox_bot::hal::SerialPort;
+use ufmt::uWrite;
+
+// Received bytes kept until the control loop reads them, a full command line
+const RX_BUFFER_SIZE: usize = 64;
+
+static RX_BUFFER: Mutex<RefCell<RxBuffer>> = Mutex::new(RefCell::new(RxBuffer::new()));
+static RX_TO_IBUS: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+pub type Usart0 = Usart<USART0, Pin<Input, PD0>, Pin<Output, PD1>>;
+
+/// Serial port on D0/D1, for text reports, binary telemetry and the command shell.
+pub struct Serial {
+    usart: Usart0,
+}
+
+impl Serial {
+    /// Takes over the USART, which must have the RX complete interrupt enabled.
+    /// With `rx_to_ibus` the received bytes are iBUS frames and the shell gets none.
+    pub fn new(usart: Usart0, rx_to_ibus: bool) -> Self {
+        avr_device::interrupt::free(|cs| RX_TO_IBUS.borrow(cs).set(rx_to_ibus));
+        Self { usart }
+    }
+}
+
+impl uWrite for Serial {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Infallible> {
+        self.usart.write_str(s)
+    }
+}
+
+impl SerialPort for Serial {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.usart.write_byte(*byte);
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        avr_device::interrupt::free(|cs| RX_BUFFER.borrow(cs).borrow_mut().pop())
+    }
+}
+
+/// Ring buffer of received bytes. Bytes arriving while it is full are dropped.
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_SIZE],
+    start: usize,
+    len: usize,
+}
+
+impl RxBuffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; RX_BUFFER_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < RX_BUFFER_SIZE {
+            self.bytes[(self.start + self.len) % RX_BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.start];
+        self.start = (self.start + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+#[avr_device::interrupt(atmega328p)]
+fn USART_RX() {
+    let byte = get_received_byte();
+    avr_device::interrupt::free(|cs| {
+        if RX_TO_IBUS.borrow(cs).get() {
+            ibus::process_byte(cs, byte);
+        } else {
+            RX_BUFFER.borrow(cs).borrow_mut().push(byte);
+        }
+    });
+}
+
+/// Returns the byte received by USART0 (UDR0), clearing the interrupt.
+fn get_received_byte() -> u8 {
+    unsafe { (*avr_device::atmega328p::USART0::ptr()).udr0.read().bits() }
+}
@@ -0,0 +1,31 @@
+use arduino_hal::Eeprom;
+use ox_bot::{hal::ConfigStorage, robot::store::RECORD_LEN};
+
+// The settings record is kept at the start of the 1 KiB EEPROM
+const EEPROM_SIZE: usize = 1024;
+const _: () = assert!(RECORD_LEN <= EEPROM_SIZE);
+
+/// Settings memory in the internal EEPROM of the ATmega328p.
+pub struct EepromStorage {
+    eeprom: Eeprom,
+}
+
+impl EepromStorage {
+    pub fn new(eeprom: Eeprom) -> Self {
+        Self { eeprom }
+    }
+}
+
+impl ConfigStorage for EepromStorage {
+    fn read(&mut self, buffer: &mut [u8]) {
+        // Out of bounds reads are ruled out above, read as erased memory anyway
+        if self.eeprom.read(0, buffer).is_err() {
+            buffer.fill(0xFF);
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Unchanged bytes are not rewritten, sparing the EEPROM cells
+        let _ = self.eeprom.write(0, bytes);
+    }
+}
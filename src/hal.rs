@@ -0,0 +1,158 @@
+use crate::robot::ppm::PositionValue;
+use core::convert::Infallible;
+use ufmt::uWrite;
+
+/// Number of channels the stick model is built from.
+pub const NUM_STICK_CHANNELS: usize = 6;
+/// Most receiver channels read, the 10 PPM channels of an unlocked FS-i6X.
+pub const MAX_RC_CHANNELS: usize = 10;
+
+/// H-bridge output driving one wheel motor.
+pub trait Motor {
+    fn forward(&mut self, value: u8);
+    fn backward(&mut self, value: u8);
+    /// Stops the motor the way the last `set_stop_mode` asked.
+    fn stop(&mut self);
+    /// Chooses how the following stops leave the motor.
+    fn set_stop_mode(&mut self, mode: StopMode);
+}
+
+/// What an H-bridge does with the motor when it stops.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopMode {
+    /// Shorts the motor terminals, so the wheel stops at once.
+    Brake,
+    /// Leaves the motor terminals open, so the wheel rolls to a stop.
+    Coast,
+}
+
+/// Motor outputs of the board. `Config::wheels` tells which wheel each one drives.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MotorId {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl MotorId {
+    pub const ALL: [MotorId; 4] = [MotorId::A, MotorId::B, MotorId::C, MotorId::D];
+}
+
+/// The set of four wheel motors of the robot.
+pub trait Motors {
+    /// Returns the motor with the given id.
+    fn get(&mut self, id: MotorId) -> &mut dyn Motor;
+}
+
+/// Channel values of the last complete frame of the receiver.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RcChannels {
+    pub values: [PositionValue; MAX_RC_CHANNELS],
+    /// Channels the frame carried, the values past them are meaningless.
+    pub count: usize,
+}
+
+impl RcChannels {
+    /// Returns the value of `channel`, if the frame carried it.
+    pub fn get(&self, channel: usize) -> Option<PositionValue> {
+        self.values[..self.count.min(MAX_RC_CHANNELS)]
+            .get(channel)
+            .copied()
+    }
+}
+
+/// A complete frame of the receiver, published as a whole so its channels all
+/// come from the same frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RcFrame {
+    pub channels: RcChannels,
+    /// Time in milliseconds the frame was received.
+    pub received_ms: u32,
+    /// Incremented by one on each frame, wrapping, to tell a new frame apart.
+    pub sequence: u16,
+}
+
+/// Counts of the frames of the receiver since start, wrapping on overflow.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameStats {
+    /// Frames accepted.
+    pub good: u32,
+    /// Frames received but rejected, such as by a glitch or a bad checksum.
+    pub bad: u32,
+    /// Frames expected from the frame period but never received.
+    pub missed: u32,
+}
+
+/// Source of radio channel values, such as a PPM or iBUS receiver.
+pub trait RcInput {
+    /// Returns the last complete frame, if any.
+    fn get_frame(&self) -> Option<RcFrame>;
+    /// Returns the counts of good, bad and missed frames since start.
+    fn get_frame_stats(&self) -> FrameStats;
+}
+
+/// Monotonic time source.
+pub trait Clock {
+    /// Returns the milliseconds elapsed since start, wrapping on overflow.
+    fn millis(&self) -> u32;
+    /// Returns the microseconds elapsed since start, wrapping on overflow.
+    fn micros(&self) -> u32;
+    /// Blocks until the next tick of the control loop. Returns the ticks elapsed
+    /// since the previous call, more than one if the loop missed some.
+    fn wait_for_tick(&self) -> u16;
+    /// Ticks the control loop `rate_hz` times per second from now on.
+    fn set_control_rate(&self, rate_hz: u16);
+}
+
+/// Sensor of the voltage of the battery pack.
+pub trait BatterySensor {
+    /// Returns the voltage of the whole pack in millivolts.
+    fn read_millivolts(&mut self) -> u16;
+}
+
+/// Indicator of the state of the robot, such as the onboard LED.
+pub trait StatusLed {
+    fn set(&mut self, on: bool);
+}
+
+/// Non-volatile memory holding the saved settings, such as the EEPROM.
+pub trait ConfigStorage {
+    /// Fills `buffer` with the bytes stored from the start of the memory.
+    fn read(&mut self, buffer: &mut [u8]);
+    /// Stores `bytes` from the start of the memory, which must be large enough.
+    fn write(&mut self, bytes: &[u8]);
+}
+
+/// Hardware the robot is built from.
+pub struct Hardware<M, R, C, S, L, B, E> {
+    pub motors: M,
+    pub rc_input: R,
+    pub clock: C,
+    pub serial: S,
+    pub led: L,
+    pub battery: B,
+    pub storage: E,
+}
+
+/// Serial port used for text reports, binary telemetry and the command shell.
+pub trait SerialPort: uWrite<Error = Infallible> {
+    /// Writes raw bytes, such as a telemetry frame.
+    fn write_bytes(&mut self, bytes: &[u8]);
+    /// Returns the next received byte, if any, without blocking.
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+/// Unwraps results that can never fail, like the writes of a `SerialPort`.
+pub trait UnwrapInfallible<T> {
+    fn unwrap_infallible(self) -> T;
+}
+
+impl<T> UnwrapInfallible<T> for Result<T, Infallible> {
+    fn unwrap_infallible(self) -> T {
+        match self {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+}
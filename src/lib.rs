@@ -0,0 +1,10 @@
+#![cfg_attr(not(test), no_std)]
+
+// Robot logic, independent of the board it runs on
+mod codec;
+pub mod crc;
+pub mod hal;
+#[cfg(test)]
+pub mod mock;
+pub mod robot;
+pub mod telemetry;
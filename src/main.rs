@@ -3,16 +3,20 @@
 #![feature(abi_avr_interrupt)]
 
 // modules
-mod robot;
+mod board;
 
+use board::{Board, FlySkyPpmPin, FlySkyReceiver};
 use core::panic::PanicInfo;
-use robot::Robot;
+use ox_bot::{
+    hal::Hardware,
+    robot::{config::Config, Robot},
+};
 
-// Baudrate of the robot serial connection
+// Baudrate of the robot serial connection, iBUS receivers require 115200
 const BAUDRATE: u32 = 115200;
 
-// Frequency of the robot processing
-const PROCESS_INTERVAL_US: u32 = 0;
+// Receiver output wired to the Arduino: PPM on D2/D3 or iBUS on RX (D0)
+const RECEIVER: FlySkyReceiver = FlySkyReceiver::Ppm(FlySkyPpmPin::D2);
 
 #[arduino_hal::entry]
 // Load peripherals of arduino
@@ -22,8 +26,21 @@ fn main() -> ! {
         None => panic!("Fail to load peripherals"),
     };
 
-    // Initialize Robot with the peripherals, baurate of serial, and process interval
-    let mut robot = Robot::new(peripherals, BAUDRATE, PROCESS_INTERVAL_US);
+    // Initialize the Arduino with the baudrate of serial and receiver output
+    let board = Board::init(peripherals, BAUDRATE, RECEIVER);
+
+    // Initialize Robot with the board and the tuning used until settings are saved,
+    // which includes the control rate and failsafe timeout
+    let hardware = Hardware {
+        motors: board.motors,
+        rc_input: board.flysky,
+        clock: board.clock,
+        serial: board.serial,
+        led: board.led,
+        battery: board.battery,
+        storage: board.storage,
+    };
+    let mut robot = Robot::new(hardware, Config::default());
     robot.start();
 }
 
@@ -0,0 +1,367 @@
+// In memory hardware for host tests. Every mock is a cheap handle over shared
+// state, so a test keeps a clone to drive inputs and inspect outputs while the
+// robot owns the other one.
+
+use crate::hal::{
+    BatterySensor, Clock, ConfigStorage, FrameStats, Motor, MotorId, Motors, RcChannels, RcFrame,
+    RcInput, SerialPort, StatusLed, StopMode, MAX_RC_CHANNELS,
+};
+use crate::robot::{flysky::MID_POSITION, ppm::PositionValue};
+use core::convert::Infallible;
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+    string::String,
+    vec::Vec,
+};
+use ufmt::uWrite;
+
+/// Last command given to a mock motor.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MotorState {
+    Forward(u8),
+    Backward(u8),
+    Stopped,
+}
+
+impl MotorState {
+    /// Returns the duty as a signed value, positive forward and negative backward.
+    pub fn signed_duty(self) -> i16 {
+        match self {
+            MotorState::Forward(value) => value as i16,
+            MotorState::Backward(value) => -(value as i16),
+            MotorState::Stopped => 0,
+        }
+    }
+}
+
+pub struct MockMotor {
+    state: Rc<Cell<MotorState>>,
+    stop_mode: Rc<Cell<StopMode>>,
+}
+
+impl Motor for MockMotor {
+    fn forward(&mut self, value: u8) {
+        self.state.set(MotorState::Forward(value));
+    }
+    fn backward(&mut self, value: u8) {
+        self.state.set(MotorState::Backward(value));
+    }
+    fn stop(&mut self) {
+        self.state.set(MotorState::Stopped);
+    }
+    fn set_stop_mode(&mut self, mode: StopMode) {
+        self.stop_mode.set(mode);
+    }
+}
+
+pub struct MockMotors {
+    motors: [MockMotor; 4],
+    states: [Rc<Cell<MotorState>>; 4],
+    stop_modes: [Rc<Cell<StopMode>>; 4],
+}
+
+impl MockMotors {
+    /// Creates four stopped motors.
+    pub fn new() -> Self {
+        let states: [Rc<Cell<MotorState>>; 4] =
+            core::array::from_fn(|_| Rc::new(Cell::new(MotorState::Stopped)));
+        let stop_modes: [Rc<Cell<StopMode>>; 4] =
+            core::array::from_fn(|_| Rc::new(Cell::new(StopMode::Coast)));
+        let motors = core::array::from_fn(|index| MockMotor {
+            state: states[index].clone(),
+            stop_mode: stop_modes[index].clone(),
+        });
+        Self {
+            motors,
+            states,
+            stop_modes,
+        }
+    }
+
+    /// Returns a handle to read the motor states after the robot takes the motors.
+    pub fn probe(&self) -> MotorProbe {
+        MotorProbe {
+            states: self.states.clone(),
+            stop_modes: self.stop_modes.clone(),
+        }
+    }
+}
+
+impl Default for MockMotors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Motors for MockMotors {
+    fn get(&mut self, id: MotorId) -> &mut dyn Motor {
+        &mut self.motors[id as usize]
+    }
+}
+
+#[derive(Clone)]
+pub struct MotorProbe {
+    states: [Rc<Cell<MotorState>>; 4],
+    stop_modes: [Rc<Cell<StopMode>>; 4],
+}
+
+impl MotorProbe {
+    /// Returns the last command given to the motor.
+    pub fn state(&self, id: MotorId) -> MotorState {
+        self.states[id as usize].get()
+    }
+
+    /// Returns the stop mode of all four motors, in `MotorId` order.
+    pub fn stop_modes(&self) -> [StopMode; 4] {
+        MotorId::ALL.map(|id| self.stop_modes[id as usize].get())
+    }
+
+    /// Returns the signed duty of all four motors, in `MotorId` order.
+    pub fn signed_duties(&self) -> [i16; 4] {
+        MotorId::ALL.map(|id| self.state(id).signed_duty())
+    }
+}
+
+/// Receiver whose frames are delivered by the test.
+#[derive(Clone)]
+pub struct MockRcInput {
+    frame: Rc<Cell<Option<RcFrame>>>,
+    frame_stats: Rc<Cell<FrameStats>>,
+}
+
+impl MockRcInput {
+    /// Creates a receiver that has not received any frame yet.
+    pub fn new() -> Self {
+        Self {
+            frame: Rc::new(Cell::new(None)),
+            frame_stats: Rc::new(Cell::new(FrameStats::default())),
+        }
+    }
+
+    /// Simulates a complete frame with the given channels received at `now_ms`.
+    pub fn receive_frame(&self, channels: &[PositionValue], now_ms: u32) {
+        let mut values = [MID_POSITION; MAX_RC_CHANNELS];
+        values[..channels.len()].copy_from_slice(channels);
+        let sequence = self
+            .frame
+            .get()
+            .map_or(0, |frame| frame.sequence.wrapping_add(1));
+        self.frame.set(Some(RcFrame {
+            channels: RcChannels {
+                values,
+                count: channels.len(),
+            },
+            received_ms: now_ms,
+            sequence,
+        }));
+        let mut stats = self.frame_stats.get();
+        stats.good += 1;
+        self.frame_stats.set(stats);
+    }
+
+    /// Simulates frames rejected by the decoder and frames never received.
+    pub fn lose_frames(&self, bad: u32, missed: u32) {
+        let mut stats = self.frame_stats.get();
+        stats.bad += bad;
+        stats.missed += missed;
+        self.frame_stats.set(stats);
+    }
+}
+
+impl Default for MockRcInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RcInput for MockRcInput {
+    fn get_frame(&self) -> Option<RcFrame> {
+        self.frame.get()
+    }
+
+    fn get_frame_stats(&self) -> FrameStats {
+        self.frame_stats.get()
+    }
+}
+
+/// Clock that only moves when the test advances it or waits for a tick.
+#[derive(Clone)]
+pub struct MockClock {
+    now_us: Rc<Cell<u32>>,
+    tick_us: Rc<Cell<u32>>,
+}
+
+impl MockClock {
+    /// Creates a clock at zero whose control ticks are `tick_us` apart.
+    pub fn new(tick_us: u32) -> Self {
+        Self {
+            now_us: Rc::new(Cell::new(0)),
+            tick_us: Rc::new(Cell::new(tick_us)),
+        }
+    }
+
+    /// Moves the clock forward by `ms` milliseconds.
+    pub fn advance(&self, ms: u32) {
+        self.advance_us(ms * 1000);
+    }
+
+    /// Returns the time between two control ticks.
+    pub fn tick_us(&self) -> u32 {
+        self.tick_us.get()
+    }
+
+    /// Moves the clock forward by `us` microseconds.
+    pub fn advance_us(&self, us: u32) {
+        self.now_us.set(self.now_us.get().wrapping_add(us));
+    }
+}
+
+impl Default for MockClock {
+    /// Returns a clock ticking at 50 Hz.
+    fn default() -> Self {
+        Self::new(20_000)
+    }
+}
+
+impl Clock for MockClock {
+    fn millis(&self) -> u32 {
+        self.now_us.get() / 1000
+    }
+
+    fn micros(&self) -> u32 {
+        self.now_us.get()
+    }
+
+    fn wait_for_tick(&self) -> u16 {
+        self.advance_us(self.tick_us.get());
+        1
+    }
+
+    fn set_control_rate(&self, rate_hz: u16) {
+        self.tick_us.set(1_000_000 / rate_hz as u32);
+    }
+}
+
+/// Battery whose voltage is set by the test.
+#[derive(Clone)]
+pub struct MockBattery {
+    millivolts: Rc<Cell<u16>>,
+}
+
+impl MockBattery {
+    /// Creates a battery at `millivolts`.
+    pub fn new(millivolts: u16) -> Self {
+        Self {
+            millivolts: Rc::new(Cell::new(millivolts)),
+        }
+    }
+
+    /// Changes the voltage of the battery.
+    pub fn set_millivolts(&self, millivolts: u16) {
+        self.millivolts.set(millivolts);
+    }
+}
+
+impl BatterySensor for MockBattery {
+    fn read_millivolts(&mut self) -> u16 {
+        self.millivolts.get()
+    }
+}
+
+/// LED whose state the test can read back.
+#[derive(Clone, Default)]
+pub struct MockLed {
+    on: Rc<Cell<bool>>,
+}
+
+impl MockLed {
+    /// Returns true if the LED is lit.
+    pub fn is_on(&self) -> bool {
+        self.on.get()
+    }
+}
+
+impl StatusLed for MockLed {
+    fn set(&mut self, on: bool) {
+        self.on.set(on);
+    }
+}
+
+/// Serial port collecting everything written to it.
+#[derive(Clone, Default)]
+pub struct MockSerial {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl MockSerial {
+    /// Queues `text` to be received by the robot.
+    pub fn send(&self, text: &str) {
+        self.input.borrow_mut().extend(text.bytes());
+    }
+
+    /// Returns and clears everything written so far, as text.
+    pub fn take_output(&self) -> String {
+        String::from_utf8_lossy(&self.take_bytes()).into_owned()
+    }
+
+    /// Returns and clears everything written so far.
+    pub fn take_bytes(&self) -> Vec<u8> {
+        self.output.take()
+    }
+}
+
+impl uWrite for MockSerial {
+    type Error = Infallible;
+
+    fn write_str(&mut self, s: &str) -> Result<(), Infallible> {
+        self.output.borrow_mut().extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl SerialPort for MockSerial {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.output.borrow_mut().extend_from_slice(bytes);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.borrow_mut().pop_front()
+    }
+}
+
+/// Settings memory of 1 KiB like the ATmega328p EEPROM, erased to 0xFF.
+#[derive(Clone)]
+pub struct MockStorage {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl MockStorage {
+    pub const SIZE: usize = 1024;
+
+    /// Overwrites the memory from the start, like an older firmware would have.
+    pub fn fill(&self, bytes: &[u8]) {
+        self.bytes.borrow_mut()[..bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+impl Default for MockStorage {
+    /// Creates an erased memory.
+    fn default() -> Self {
+        Self {
+            bytes: Rc::new(RefCell::new(std::vec![0xFF; Self::SIZE])),
+        }
+    }
+}
+
+impl ConfigStorage for MockStorage {
+    fn read(&mut self, buffer: &mut [u8]) {
+        buffer.copy_from_slice(&self.bytes.borrow()[..buffer.len()]);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.fill(bytes);
+    }
+}
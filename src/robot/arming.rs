@@ -0,0 +1,244 @@
+use crate::hal::NUM_STICK_CHANNELS;
+use crate::robot::flysky::channel_map::ChannelFunction;
+use crate::robot::flysky::{
+    self, Switch, SwitchPosition, CHANNEL_0, CHANNEL_1, CHANNEL_2, CHANNEL_3,
+};
+use crate::robot::ppm::PositionValue;
+
+// Time the arming or disarming gesture must be held
+const GESTURE_HOLD_MS: u32 = 1000;
+// Stick positions that count as pushed to an end, for the gestures
+const LOW_POSITION: PositionValue = 1100;
+const HIGH_POSITION: PositionValue = 1900;
+
+/// How the pilot arms and disarms the robot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArmMode {
+    /// Left stick down-right held for a second arms, down-left disarms. Both need
+    /// the right stick centered.
+    Gesture,
+    /// One of the switches SWA to SWD of the channel map, high is armed. The switch
+    /// must be seen low once before it can arm.
+    Switch(ChannelFunction),
+}
+
+impl ArmMode {
+    /// Returns the number of the arm switch in the shell and the saved settings,
+    /// 0 for the gesture and 1 to 4 for SWA to SWD.
+    pub fn switch_code(self) -> u8 {
+        match self {
+            ArmMode::Gesture => 0,
+            ArmMode::Switch(switch) => ChannelFunction::switch_code(Some(switch)),
+        }
+    }
+
+    /// Returns the mode arming with the switch of the given number, or the gesture
+    /// for 0. Returns None if the number is not one.
+    pub fn from_switch_code(code: u8) -> Option<ArmMode> {
+        ChannelFunction::from_switch_code(code)
+            .map(|switch| switch.map_or(ArmMode::Gesture, ArmMode::Switch))
+    }
+}
+
+/// Whether the robot is allowed to drive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArmingState {
+    Disarmed,
+    Armed,
+}
+
+impl ArmingState {
+    /// Returns true if the motors may be driven.
+    pub fn is_armed(self) -> bool {
+        self == ArmingState::Armed
+    }
+}
+
+pub struct Arming {
+    mode: ArmMode,
+    center_band: u16,
+    state: ArmingState,
+    gesture_since_ms: Option<u32>,
+    switch_released: bool,
+}
+
+impl Arming {
+    /// Creates an arming state machine using `mode`, which takes the right stick
+    /// as centered within `center_band` of the middle. It starts disarmed.
+    pub fn new(mode: ArmMode, center_band: u16) -> Self {
+        Self {
+            mode,
+            center_band,
+            state: ArmingState::Disarmed,
+            gesture_since_ms: None,
+            switch_released: false,
+        }
+    }
+
+    /// Changes how the pilot arms and disarms the robot. The state is kept, and a
+    /// switch must be seen low before it can arm.
+    pub fn set_mode(&mut self, mode: ArmMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.gesture_since_ms = None;
+            self.switch_released = false;
+        }
+    }
+
+    /// Changes how far from the middle the right stick still reads centered.
+    pub fn set_center_band(&mut self, center_band: u16) {
+        self.center_band = center_band;
+    }
+
+    /// Updates the state with the channels of a valid frame at `now_ms` and returns it.
+    /// `switch` is the arm switch decoded from the same frame, if the mode uses one.
+    /// Arming also requires the right stick centered, so the robot never lurches.
+    pub fn update(
+        &mut self,
+        channels: &[PositionValue; NUM_STICK_CHANNELS],
+        switch: Option<Switch>,
+        now_ms: u32,
+    ) -> ArmingState {
+        match self.mode {
+            ArmMode::Gesture => self.update_gesture(channels, now_ms),
+            ArmMode::Switch(_) => self.update_switch(channels, switch),
+        }
+        self.state
+    }
+
+    /// Arms or disarms at once, as requested from the serial shell.
+    pub fn force(&mut self, state: ArmingState) {
+        self.state = state;
+        self.gesture_since_ms = None;
+    }
+
+    /// Toggles the state once the gesture towards the other state is held long enough.
+    fn update_gesture(&mut self, channels: &[PositionValue; NUM_STICK_CHANNELS], now_ms: u32) {
+        let throttle_low = channels[CHANNEL_2] < LOW_POSITION;
+        let gesture = match self.state {
+            ArmingState::Disarmed => {
+                throttle_low
+                    && channels[CHANNEL_3] > HIGH_POSITION
+                    && drive_centered(channels, self.center_band)
+            }
+            ArmingState::Armed => {
+                throttle_low
+                    && channels[CHANNEL_3] < LOW_POSITION
+                    && drive_centered(channels, self.center_band)
+            }
+        };
+        if !gesture {
+            self.gesture_since_ms = None;
+            return;
+        }
+
+        let since_ms = *self.gesture_since_ms.get_or_insert(now_ms);
+        if now_ms.wrapping_sub(since_ms) >= GESTURE_HOLD_MS {
+            self.gesture_since_ms = None;
+            self.state = match self.state {
+                ArmingState::Disarmed => ArmingState::Armed,
+                ArmingState::Armed => ArmingState::Disarmed,
+            };
+        }
+    }
+
+    /// Follows the switch position, arming only on a low to high transition.
+    /// A switch missing from the channel map or in the middle changes nothing.
+    fn update_switch(
+        &mut self,
+        channels: &[PositionValue; NUM_STICK_CHANNELS],
+        switch: Option<Switch>,
+    ) {
+        match switch.map(Switch::position) {
+            Some(SwitchPosition::Low) => {
+                self.switch_released = true;
+                self.state = ArmingState::Disarmed;
+            }
+            Some(SwitchPosition::High)
+                if self.switch_released && drive_centered(channels, self.center_band) =>
+            {
+                self.switch_released = false;
+                self.state = ArmingState::Armed;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns true if both axes of the right stick are within `center_band` of the middle.
+pub fn drive_centered(channels: &[PositionValue; NUM_STICK_CHANNELS], center_band: u16) -> bool {
+    [channels[CHANNEL_0], channels[CHANNEL_1]]
+        .iter()
+        .all(|value| flysky::is_centered(*value, center_band))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTERED: [PositionValue; NUM_STICK_CHANNELS] = [1500, 1500, 1000, 1500, 1500, 1000];
+    const ARM_GESTURE: [PositionValue; NUM_STICK_CHANNELS] = [1500, 1500, 1000, 2000, 1500, 1000];
+    const DISARM_GESTURE: [PositionValue; NUM_STICK_CHANNELS] =
+        [1500, 1500, 1000, 1000, 1500, 1000];
+
+    #[test]
+    fn gesture_must_be_held_for_a_second() {
+        let mut arming = Arming::new(ArmMode::Gesture, 50);
+        assert_eq!(arming.update(&ARM_GESTURE, None, 0), ArmingState::Disarmed);
+        assert_eq!(
+            arming.update(&ARM_GESTURE, None, 980),
+            ArmingState::Disarmed
+        );
+        // Releasing the stick restarts the hold
+        assert_eq!(arming.update(&CENTERED, None, 1000), ArmingState::Disarmed);
+        assert_eq!(
+            arming.update(&ARM_GESTURE, None, 1020),
+            ArmingState::Disarmed
+        );
+        assert_eq!(arming.update(&ARM_GESTURE, None, 2020), ArmingState::Armed);
+
+        assert_eq!(
+            arming.update(&DISARM_GESTURE, None, 3000),
+            ArmingState::Armed
+        );
+        assert_eq!(
+            arming.update(&DISARM_GESTURE, None, 4000),
+            ArmingState::Disarmed
+        );
+    }
+
+    #[test]
+    fn gesture_does_not_arm_while_driving() {
+        let mut arming = Arming::new(ArmMode::Gesture, 50);
+        let mut channels = ARM_GESTURE;
+        channels[CHANNEL_1] = 1800;
+        arming.update(&channels, None, 0);
+        assert_eq!(arming.update(&channels, None, 2000), ArmingState::Disarmed);
+    }
+
+    #[test]
+    fn switch_must_be_low_before_arming() {
+        let mut arming = Arming::new(ArmMode::Switch(ChannelFunction::Swa), 50);
+        let low = Some(Switch::TwoWay(SwitchPosition::Low));
+        let high = Some(Switch::TwoWay(SwitchPosition::High));
+        assert_eq!(arming.update(&CENTERED, high, 0), ArmingState::Disarmed);
+        assert_eq!(arming.update(&CENTERED, low, 20), ArmingState::Disarmed);
+        assert_eq!(arming.update(&CENTERED, high, 40), ArmingState::Armed);
+        // A switch lost from the channel map keeps the state
+        assert_eq!(arming.update(&CENTERED, None, 60), ArmingState::Armed);
+        assert_eq!(arming.update(&CENTERED, low, 80), ArmingState::Disarmed);
+    }
+
+    #[test]
+    fn switch_codes_select_modes() {
+        for code in 0..=4 {
+            let mode = ArmMode::from_switch_code(code).unwrap();
+            assert_eq!(mode.switch_code(), code);
+        }
+        assert_eq!(
+            ArmMode::from_switch_code(4),
+            Some(ArmMode::Switch(ChannelFunction::Swd))
+        );
+        assert_eq!(ArmMode::from_switch_code(5), None);
+    }
+}
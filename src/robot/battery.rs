@@ -0,0 +1,160 @@
+// Weight of the previous value in the moving average of the readings
+const FILTER_WEIGHT: i32 = 8;
+// Below this the A2 divider is taken as not fitted, no pack reads that low
+const NO_SENSOR_MV: u16 = 1000;
+
+/// Thresholds of the battery monitor, in millivolts of the whole pack.
+/// Setting both to zero disables the power limiting.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BatteryConfig {
+    /// Below this voltage the power starts being limited.
+    pub warning_mv: u16,
+    /// Below this voltage the motors are stopped until the robot is restarted.
+    pub cutoff_mv: u16,
+    /// Power left just above the cutoff, in percent.
+    pub min_power: u8,
+}
+
+impl Default for BatteryConfig {
+    /// Returns thresholds for the 6 cell 7.2V NiMH pack, 1.1V and 1.0V per cell.
+    fn default() -> Self {
+        Self {
+            warning_mv: 6600,
+            cutoff_mv: 6000,
+            min_power: 40,
+        }
+    }
+}
+
+/// Charge level of the battery.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BatteryState {
+    Ok,
+    /// Under the warning voltage, the power is limited.
+    Low,
+    /// Went under the cutoff voltage, the motors must be stopped.
+    Cutoff,
+}
+
+/// Filters the battery voltage and derives the power the motors may use.
+pub struct BatteryMonitor {
+    config: BatteryConfig,
+    filtered_mv: Option<u16>,
+    state: BatteryState,
+}
+
+impl BatteryMonitor {
+    /// Creates a monitor with no reading yet.
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            config,
+            filtered_mv: None,
+            state: BatteryState::Ok,
+        }
+    }
+
+    /// Replaces the thresholds, keeping the filtered voltage.
+    pub fn set_config(&mut self, config: BatteryConfig) {
+        self.config = config;
+    }
+
+    /// Adds a reading of the pack voltage and returns the resulting state.
+    /// The cutoff is latched, a pack recovering once unloaded never restarts the motors.
+    /// A reading under 1V means there is no divider, and the pack is taken as Ok.
+    pub fn update(&mut self, sample_mv: u16) -> BatteryState {
+        let filtered_mv = match self.filtered_mv {
+            Some(previous) => {
+                let previous = previous as i32;
+                (previous + (sample_mv as i32 - previous) / FILTER_WEIGHT) as u16
+            }
+            None => sample_mv,
+        };
+        self.filtered_mv = Some(filtered_mv);
+
+        self.state = if self.state == BatteryState::Cutoff {
+            BatteryState::Cutoff
+        } else if filtered_mv < NO_SENSOR_MV {
+            BatteryState::Ok
+        } else if filtered_mv < self.config.cutoff_mv {
+            BatteryState::Cutoff
+        } else if filtered_mv < self.config.warning_mv {
+            BatteryState::Low
+        } else {
+            BatteryState::Ok
+        };
+        self.state
+    }
+
+    /// Returns the filtered pack voltage in millivolts, zero before the first reading.
+    pub fn millivolts(&self) -> u16 {
+        self.filtered_mv.unwrap_or(0)
+    }
+
+    /// Returns the power the motors may use in percent. It goes down linearly from
+    /// 100 at the warning voltage to `min_power` at the cutoff, and 0 once cut off.
+    pub fn power_percent(&self) -> u8 {
+        let BatteryConfig {
+            warning_mv,
+            cutoff_mv,
+            min_power,
+        } = self.config;
+        match self.state {
+            BatteryState::Ok => 100,
+            BatteryState::Cutoff => 0,
+            BatteryState::Low => {
+                let span = warning_mv.saturating_sub(cutoff_mv).max(1) as u32;
+                let above_cutoff = self.millivolts().saturating_sub(cutoff_mv) as u32;
+                let min_power = min_power.min(100) as u32;
+                (min_power + (100 - min_power) * above_cutoff.min(span) / span) as u8
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settle(monitor: &mut BatteryMonitor, sample_mv: u16) -> BatteryState {
+        let mut state = monitor.update(sample_mv);
+        for _ in 0..100 {
+            state = monitor.update(sample_mv);
+        }
+        state
+    }
+
+    #[test]
+    fn filters_out_short_dips() {
+        let mut monitor = BatteryMonitor::new(BatteryConfig::default());
+        assert_eq!(monitor.update(7200), BatteryState::Ok);
+        assert_eq!(monitor.update(5000), BatteryState::Ok);
+        assert_eq!(monitor.millivolts(), 6925);
+    }
+
+    #[test]
+    fn limits_power_progressively_when_low() {
+        let mut monitor = BatteryMonitor::new(BatteryConfig::default());
+        assert_eq!(settle(&mut monitor, 7000), BatteryState::Ok);
+        assert_eq!(monitor.power_percent(), 100);
+
+        assert_eq!(settle(&mut monitor, 6300), BatteryState::Low);
+        assert!((68..=72).contains(&monitor.power_percent()));
+    }
+
+    #[test]
+    fn cutoff_is_latched() {
+        let mut monitor = BatteryMonitor::new(BatteryConfig::default());
+        assert_eq!(settle(&mut monitor, 5900), BatteryState::Cutoff);
+        assert_eq!(monitor.power_percent(), 0);
+        assert_eq!(settle(&mut monitor, 7200), BatteryState::Cutoff);
+    }
+
+    #[test]
+    fn ignores_a_missing_divider() {
+        let mut monitor = BatteryMonitor::new(BatteryConfig::default());
+        assert_eq!(settle(&mut monitor, 0), BatteryState::Ok);
+        assert_eq!(monitor.power_percent(), 100);
+        // A divider read once still cuts off
+        assert_eq!(settle(&mut monitor, 5900), BatteryState::Cutoff);
+    }
+}
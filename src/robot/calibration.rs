@@ -0,0 +1,243 @@
+use crate::hal::NUM_STICK_CHANNELS;
+use crate::robot::flysky::{
+    CHANNEL_0, CHANNEL_1, CHANNEL_2, CHANNEL_3, MAX_POSITION, MID_POSITION, MIN_POSITION,
+};
+use crate::robot::ppm::PositionValue;
+
+// Channels of the sticks that spring back to the center, whose center is where
+// they rest. The others (throttle and knobs) take the middle of their travel.
+const SELF_CENTERING: [bool; NUM_STICK_CHANNELS] = [true, true, false, true, false, false];
+// Travel every channel must cover before the calibration can finish
+const MIN_TRAVEL_US: u16 = 600;
+// Smallest accepted distance between the center and each end
+pub const MIN_HALF_TRAVEL_US: u16 = 200;
+// Movement of the channels still counted as resting
+const REST_TOLERANCE_US: u16 = 8;
+// Time the sticks must rest once swept to finish the calibration
+const REST_HOLD_MS: u32 = 1000;
+// Time allowed to sweep the sticks before the calibration is abandoned
+const CALIBRATION_TIMEOUT_MS: u32 = 30_000;
+// Time both sticks must be held down-left to start a calibration
+const GESTURE_HOLD_MS: u32 = 3000;
+// Stick position that counts as pushed to the end for the gesture
+const LOW_POSITION: PositionValue = 1100;
+
+/// Pulse widths a channel of the transmitter actually reads at its ends and at rest.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChannelCalibration {
+    pub min: PositionValue,
+    pub center: PositionValue,
+    pub max: PositionValue,
+}
+
+impl ChannelCalibration {
+    /// The nominal 1000/1500/2000 µs of an ideal transmitter.
+    pub const NOMINAL: Self = Self {
+        min: MIN_POSITION,
+        center: MID_POSITION,
+        max: MAX_POSITION,
+    };
+
+    /// Returns true if the center is far enough from both ends to map the channel.
+    pub fn is_valid(&self) -> bool {
+        self.min.saturating_add(MIN_HALF_TRAVEL_US) <= self.center
+            && self.center.saturating_add(MIN_HALF_TRAVEL_US) <= self.max
+    }
+}
+
+impl Default for ChannelCalibration {
+    fn default() -> Self {
+        Self::NOMINAL
+    }
+}
+
+/// Progress of a calibration after a frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationStep {
+    /// Still waiting for the sticks to be swept and released.
+    Sweeping,
+    Done([ChannelCalibration; NUM_STICK_CHANNELS]),
+    /// The sticks were not swept and released in time.
+    TimedOut,
+}
+
+/// Records the extremes and the rest position of every channel while the pilot
+/// sweeps each stick and knob to both ends and then releases the sticks.
+pub struct Calibrator {
+    started_ms: u32,
+    min: [PositionValue; NUM_STICK_CHANNELS],
+    max: [PositionValue; NUM_STICK_CHANNELS],
+    rest: [PositionValue; NUM_STICK_CHANNELS],
+    rest_since_ms: u32,
+}
+
+impl Calibrator {
+    /// Starts a calibration at `now_ms`, before any frame is seen.
+    pub fn new(now_ms: u32) -> Self {
+        Self {
+            started_ms: now_ms,
+            min: [PositionValue::MAX; NUM_STICK_CHANNELS],
+            max: [PositionValue::MIN; NUM_STICK_CHANNELS],
+            rest: [0; NUM_STICK_CHANNELS],
+            rest_since_ms: now_ms,
+        }
+    }
+
+    /// Adds the channels of a valid frame at `now_ms` and returns the progress.
+    pub fn update(
+        &mut self,
+        channels: &[PositionValue; NUM_STICK_CHANNELS],
+        now_ms: u32,
+    ) -> CalibrationStep {
+        if now_ms.wrapping_sub(self.started_ms) > CALIBRATION_TIMEOUT_MS {
+            return CalibrationStep::TimedOut;
+        }
+        for (channel, value) in channels.iter().enumerate() {
+            self.min[channel] = self.min[channel].min(*value);
+            self.max[channel] = self.max[channel].max(*value);
+        }
+
+        let moved = channels
+            .iter()
+            .zip(self.rest)
+            .any(|(value, rest)| value.abs_diff(rest) > REST_TOLERANCE_US);
+        if moved {
+            self.rest = *channels;
+            self.rest_since_ms = now_ms;
+            return CalibrationStep::Sweeping;
+        }
+        if now_ms.wrapping_sub(self.rest_since_ms) < REST_HOLD_MS {
+            return CalibrationStep::Sweeping;
+        }
+
+        let mut result = [ChannelCalibration::default(); NUM_STICK_CHANNELS];
+        for (channel, calibration) in result.iter_mut().enumerate() {
+            let (min, max) = (self.min[channel], self.max[channel]);
+            if max.saturating_sub(min) < MIN_TRAVEL_US {
+                return CalibrationStep::Sweeping;
+            }
+            let center = if SELF_CENTERING[channel] {
+                self.rest[channel]
+            } else {
+                min + (max - min) / 2
+            };
+            *calibration = ChannelCalibration { min, center, max };
+            // A self-centering stick resting away from the middle is still held
+            if !calibration.is_valid() {
+                return CalibrationStep::Sweeping;
+            }
+        }
+        CalibrationStep::Done(result)
+    }
+}
+
+/// Detects both sticks held down-left, which starts a calibration while disarmed.
+#[derive(Default)]
+pub struct CalibrationGesture {
+    since_ms: Option<u32>,
+    fired: bool,
+}
+
+impl CalibrationGesture {
+    /// Updates with the channels of a valid frame at `now_ms`. Returns true once
+    /// the gesture has been held long enough, then not again until it is released.
+    pub fn update(&mut self, channels: &[PositionValue; NUM_STICK_CHANNELS], now_ms: u32) -> bool {
+        let held = [CHANNEL_0, CHANNEL_1, CHANNEL_2, CHANNEL_3]
+            .iter()
+            .all(|channel| channels[*channel] < LOW_POSITION);
+        if !held {
+            self.since_ms = None;
+            self.fired = false;
+            return false;
+        }
+        let since_ms = *self.since_ms.get_or_insert(now_ms);
+        if self.fired || now_ms.wrapping_sub(since_ms) < GESTURE_HOLD_MS {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REST: [PositionValue; NUM_STICK_CHANNELS] = [1512, 1488, 990, 1506, 1500, 1000];
+
+    /// Feeds `channels` every 20 ms for `duration_ms` and returns the last step.
+    fn hold(
+        calibrator: &mut Calibrator,
+        channels: [PositionValue; NUM_STICK_CHANNELS],
+        now_ms: &mut u32,
+        duration_ms: u32,
+    ) -> CalibrationStep {
+        let mut step = CalibrationStep::Sweeping;
+        for _ in 0..duration_ms / 20 {
+            *now_ms += 20;
+            step = calibrator.update(&channels, *now_ms);
+        }
+        step
+    }
+
+    #[test]
+    fn records_ends_and_rest_centers() {
+        let mut calibrator = Calibrator::new(0);
+        let mut now_ms = 0;
+        // Resting before the sticks are swept does not finish it
+        assert_eq!(
+            hold(&mut calibrator, REST, &mut now_ms, 2000),
+            CalibrationStep::Sweeping
+        );
+        hold(&mut calibrator, [988; NUM_STICK_CHANNELS], &mut now_ms, 200);
+        hold(
+            &mut calibrator,
+            [2012; NUM_STICK_CHANNELS],
+            &mut now_ms,
+            200,
+        );
+        assert_eq!(
+            hold(&mut calibrator, REST, &mut now_ms, 500),
+            CalibrationStep::Sweeping
+        );
+
+        let CalibrationStep::Done(result) = hold(&mut calibrator, REST, &mut now_ms, 600) else {
+            panic!("calibration did not finish");
+        };
+        assert_eq!(
+            result[CHANNEL_0],
+            ChannelCalibration {
+                min: 988,
+                center: 1512,
+                max: 2012
+            }
+        );
+        // The throttle does not spring back, its center is the middle of its travel
+        assert_eq!(result[CHANNEL_2].center, 1500);
+    }
+
+    #[test]
+    fn times_out_without_a_sweep() {
+        let mut calibrator = Calibrator::new(0);
+        let mut now_ms = 0;
+        assert_eq!(
+            hold(
+                &mut calibrator,
+                REST,
+                &mut now_ms,
+                CALIBRATION_TIMEOUT_MS + 100
+            ),
+            CalibrationStep::TimedOut
+        );
+    }
+
+    #[test]
+    fn gesture_fires_once_per_hold() {
+        let mut gesture = CalibrationGesture::default();
+        let down_left = [1000, 1000, 1000, 1000, 1500, 1000];
+        assert!(!gesture.update(&down_left, 0));
+        assert!(gesture.update(&down_left, GESTURE_HOLD_MS));
+        assert!(!gesture.update(&down_left, GESTURE_HOLD_MS + 20));
+        assert!(!gesture.update(&REST, GESTURE_HOLD_MS + 40));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::hal::NUM_STICK_CHANNELS;
+use crate::robot::arming::ArmMode;
+use crate::robot::battery::BatteryConfig;
+use crate::robot::calibration::ChannelCalibration;
+use crate::robot::flysky::channel_map::ChannelMap;
+use crate::robot::kinematics::{DriveMode, Kinematics};
+use crate::robot::ramp::RampLimits;
+use crate::robot::shaping::AxisShape;
+use crate::robot::speed::SpeedConfig;
+use crate::robot::stopping::StopConfig;
+use crate::robot::wheels::WheelMap;
+
+/// Tuning of the robot.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    /// Receiver channel each function of the sticks is read from.
+    pub channel_map: ChannelMap,
+    /// Shaping of each channel, indexed by channel number.
+    pub axes: [AxisShape; NUM_STICK_CHANNELS],
+    /// Range the transmitter actually reads on each channel.
+    pub calibration: [ChannelCalibration; NUM_STICK_CHANNELS],
+    /// Distance from the middle, in µs, a stick still reads centered within.
+    pub center_band: u16,
+    /// Kinematics of the chassis, or the switch choosing them.
+    pub drive: DriveMode,
+    /// Speed limits of the VRA knob and the gears.
+    pub speed: SpeedConfig,
+    /// How the wheels stop.
+    pub stop: StopConfig,
+    /// Slew rate limits of the wheel duty.
+    pub ramp: RampLimits,
+    /// How the robot is armed before it drives.
+    pub arming: ArmMode,
+    /// Time without a complete frame before the motors are stopped.
+    pub failsafe_timeout_ms: u16,
+    /// Steps of the control loop per second. The ramp limits are per step.
+    pub control_rate_hz: u8,
+    /// Thresholds of the battery monitor.
+    pub battery: BatteryConfig,
+    /// Binary telemetry frames sent per second, 0 disables them.
+    pub telemetry_rate_hz: u8,
+    /// Motor driving each wheel, and which ones are wired backwards.
+    pub wheels: WheelMap,
+    /// Duty each motor starts turning at, indexed by `MotorId`. Smaller duties
+    /// are scaled up to it.
+    pub motor_min_duty: [u8; 4],
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            channel_map: ChannelMap::MODE_2,
+            axes: [AxisShape::default(); NUM_STICK_CHANNELS],
+            calibration: [ChannelCalibration::NOMINAL; NUM_STICK_CHANNELS],
+            center_band: 50,
+            drive: DriveMode::Fixed(Kinematics::Mecanum),
+            speed: SpeedConfig::default(),
+            stop: StopConfig::default(),
+            ramp: RampLimits::default(),
+            arming: ArmMode::Gesture,
+            failsafe_timeout_ms: 250,
+            control_rate_hz: 50,
+            battery: BatteryConfig::default(),
+            telemetry_rate_hz: 10,
+            wheels: WheelMap::default(),
+            motor_min_duty: [0; 4],
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::robot::helper::MAX_POTENCY;
+
+// Largest minimum duty, so every motor keeps some range of speeds
+pub const MAX_MIN_DUTY: u8 = 200;
+
+/// Rescales a signed duty into the range the motor turns in: the smallest non-zero
+/// duty becomes `min_duty` and full scale stays full scale. Zero stays zero, so a
+/// stopped wheel does not whine.
+pub fn compensate(duty: i16, min_duty: u8) -> i16 {
+    if duty == 0 {
+        return 0;
+    }
+    let full_scale = MAX_POTENCY as i32;
+    let min_duty = min_duty.min(MAX_MIN_DUTY) as i32;
+    let magnitude = (duty.unsigned_abs() as i32).min(full_scale);
+    let compensated = (min_duty + magnitude * (full_scale - min_duty) / full_scale) as i16;
+    if duty > 0 {
+        compensated
+    } else {
+        -compensated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rescales_into_the_range_the_motor_turns_in() {
+        assert_eq!(compensate(0, 70), 0);
+        assert_eq!(compensate(1, 70), 70);
+        assert_eq!(compensate(-1, 70), -70);
+        assert_eq!(compensate(128, 70), 162);
+        assert_eq!(compensate(255, 70), 255);
+        assert_eq!(compensate(-255, 70), -255);
+        // Without a min duty the duty is unchanged
+        for duty in -255..=255 {
+            assert_eq!(compensate(duty, 0), duty);
+        }
+        // Larger duties never turn slower
+        let duties: std::vec::Vec<i16> = (1..=255).map(|duty| compensate(duty, 80)).collect();
+        assert!(duties.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
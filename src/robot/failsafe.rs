@@ -0,0 +1,141 @@
+use crate::robot::flysky::Signal;
+
+/// State of the signal loss failsafe.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FailsafeState {
+    /// Fresh frames are arriving and the robot may drive.
+    Ok,
+    /// No complete frame within the timeout, motors must be stopped.
+    SignalLost,
+    /// Signal is back, waiting for the sticks to be centered before resuming.
+    WaitingForCenter,
+}
+
+impl FailsafeState {
+    /// Returns true if the motors must be held stopped.
+    pub fn is_engaged(self) -> bool {
+        self != FailsafeState::Ok
+    }
+}
+
+pub struct Failsafe {
+    timeout_ms: u32,
+    state: FailsafeState,
+}
+
+impl Failsafe {
+    /// Creates a failsafe that engages when no frame arrives within `timeout_ms`.
+    /// It starts engaged, so the robot never drives before the first frame.
+    pub fn new(timeout_ms: u32) -> Self {
+        Self {
+            timeout_ms,
+            state: FailsafeState::SignalLost,
+        }
+    }
+
+    /// Returns the time a frame is considered fresh for.
+    pub fn timeout_ms(&self) -> u32 {
+        self.timeout_ms
+    }
+
+    /// Changes the time without a frame before the failsafe engages.
+    pub fn set_timeout_ms(&mut self, timeout_ms: u32) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    /// Updates the state with the latest signal status and returns it.
+    pub fn update(&mut self, signal: &Signal, sticks_centered: bool) -> FailsafeState {
+        self.state = if !signal.is_valid(self.timeout_ms) {
+            FailsafeState::SignalLost
+        } else if self.state == FailsafeState::Ok || sticks_centered {
+            FailsafeState::Ok
+        } else {
+            FailsafeState::WaitingForCenter
+        };
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::FrameStats;
+
+    const TIMEOUT_MS: u32 = 500;
+
+    fn signal(now_ms: u32, last_frame_ms: Option<u32>) -> Signal {
+        Signal {
+            now_ms,
+            last_frame_ms,
+            new_frame: false,
+            frames: FrameStats::default(),
+        }
+    }
+
+    #[test]
+    fn engaged_until_first_frame() {
+        let mut failsafe = Failsafe::new(TIMEOUT_MS);
+        assert_eq!(
+            failsafe.update(&signal(0, None), true),
+            FailsafeState::SignalLost
+        );
+        assert_eq!(
+            failsafe.update(&signal(1000, None), true),
+            FailsafeState::SignalLost
+        );
+        assert_eq!(
+            failsafe.update(&signal(1020, Some(1000)), true),
+            FailsafeState::Ok
+        );
+    }
+
+    #[test]
+    fn engages_after_timeout() {
+        let mut failsafe = Failsafe::new(TIMEOUT_MS);
+        failsafe.update(&signal(100, Some(90)), true);
+        assert_eq!(
+            failsafe.update(&signal(590, Some(90)), false),
+            FailsafeState::Ok
+        );
+        assert_eq!(
+            failsafe.update(&signal(591, Some(90)), false),
+            FailsafeState::SignalLost
+        );
+    }
+
+    #[test]
+    fn requires_centered_sticks_to_resume() {
+        let mut failsafe = Failsafe::new(TIMEOUT_MS);
+        failsafe.update(&signal(2000, Some(1000)), false);
+        assert_eq!(
+            failsafe.update(&signal(2020, Some(2010)), false),
+            FailsafeState::WaitingForCenter
+        );
+        assert_eq!(
+            failsafe.update(&signal(2040, Some(2030)), false),
+            FailsafeState::WaitingForCenter
+        );
+        assert_eq!(
+            failsafe.update(&signal(2060, Some(2050)), true),
+            FailsafeState::Ok
+        );
+        assert_eq!(
+            failsafe.update(&signal(2080, Some(2070)), false),
+            FailsafeState::Ok
+        );
+    }
+
+    #[test]
+    fn survives_clock_wraparound() {
+        let mut failsafe = Failsafe::new(TIMEOUT_MS);
+        failsafe.update(&signal(u32::MAX - 10, Some(u32::MAX - 20)), true);
+        assert_eq!(
+            failsafe.update(&signal(100, Some(u32::MAX - 20)), false),
+            FailsafeState::Ok
+        );
+        assert_eq!(
+            failsafe.update(&signal(600, Some(u32::MAX - 20)), false),
+            FailsafeState::SignalLost
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use super::{MID_POSITION, MIN_POSITION};
+use crate::hal::{RcChannels, NUM_STICK_CHANNELS};
+use crate::robot::ppm::PositionValue;
+
+// Switches of the FS-i6X, SWA to SWD
+pub const NUM_SWITCHES: usize = 4;
+pub const NUM_CHANNEL_FUNCTIONS: usize = NUM_STICK_CHANNELS + NUM_SWITCHES;
+
+/// What a channel controls. The sticks come first, in the order of the stick model
+/// (`CHANNEL_0` to `CHANNEL_5`), then the switches.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChannelFunction {
+    /// Right stick horizontal, sideways.
+    Lateral,
+    /// Right stick vertical, forward and backward.
+    Forward,
+    /// Left stick vertical, only used by the arming gesture.
+    Throttle,
+    /// Left stick horizontal, rotation.
+    Turn,
+    Vra,
+    Vrb,
+    /// Two position switches.
+    Swa,
+    Swb,
+    /// Three position switch.
+    Swc,
+    Swd,
+}
+
+impl ChannelFunction {
+    pub const ALL: [ChannelFunction; NUM_CHANNEL_FUNCTIONS] = [
+        ChannelFunction::Lateral,
+        ChannelFunction::Forward,
+        ChannelFunction::Throttle,
+        ChannelFunction::Turn,
+        ChannelFunction::Vra,
+        ChannelFunction::Vrb,
+        ChannelFunction::Swa,
+        ChannelFunction::Swb,
+        ChannelFunction::Swc,
+        ChannelFunction::Swd,
+    ];
+    /// Switches that may select a setting, numbered from 1 in the settings.
+    pub const SWITCHES: [ChannelFunction; NUM_SWITCHES] = [
+        ChannelFunction::Swa,
+        ChannelFunction::Swb,
+        ChannelFunction::Swc,
+        ChannelFunction::Swd,
+    ];
+
+    /// Returns the name used by the serial shell.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChannelFunction::Lateral => "lateral",
+            ChannelFunction::Forward => "forward",
+            ChannelFunction::Throttle => "throttle",
+            ChannelFunction::Turn => "turn",
+            ChannelFunction::Vra => "vra",
+            ChannelFunction::Vrb => "vrb",
+            ChannelFunction::Swa => "swa",
+            ChannelFunction::Swb => "swb",
+            ChannelFunction::Swc => "swc",
+            ChannelFunction::Swd => "swd",
+        }
+    }
+
+    /// Returns the value read when the receiver does not send the channel: the
+    /// throttle down, switches up (off) and the other sticks centered.
+    fn rest_value(self) -> PositionValue {
+        match self {
+            ChannelFunction::Throttle
+            | ChannelFunction::Swa
+            | ChannelFunction::Swb
+            | ChannelFunction::Swc
+            | ChannelFunction::Swd => MIN_POSITION,
+            _ => MID_POSITION,
+        }
+    }
+
+    /// Returns the function with the given shell name.
+    pub fn from_name(name: &str) -> Option<ChannelFunction> {
+        ChannelFunction::ALL
+            .into_iter()
+            .find(|function| function.name() == name)
+    }
+
+    /// Returns the number of a setting's switch in the shell and the saved settings,
+    /// 0 for none and 1 to 4 for SWA to SWD.
+    pub fn switch_code(switch: Option<ChannelFunction>) -> u8 {
+        switch
+            .and_then(|function| {
+                ChannelFunction::SWITCHES
+                    .iter()
+                    .position(|sw| *sw == function)
+            })
+            .map_or(0, |index| index as u8 + 1)
+    }
+
+    /// Returns the switch, or none, with the given number. Returns None if the
+    /// number is not one.
+    pub fn from_switch_code(code: u8) -> Option<Option<ChannelFunction>> {
+        match code {
+            0 => Some(None),
+            _ => ChannelFunction::SWITCHES
+                .get(code as usize - 1)
+                .copied()
+                .map(Some),
+        }
+    }
+}
+
+/// Receiver channel a function is read from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChannelSource {
+    /// Index of the receiver channel, 0 for CH1.
+    pub channel: u8,
+    /// Mirrors the value around the center, for a channel moving the wrong way.
+    pub reverse: bool,
+}
+
+impl ChannelSource {
+    const fn new(channel: u8) -> Self {
+        Self {
+            channel,
+            reverse: false,
+        }
+    }
+}
+
+/// Table from each `ChannelFunction` to the receiver channel it is read from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChannelMap {
+    pub sources: [ChannelSource; NUM_CHANNEL_FUNCTIONS],
+}
+
+impl ChannelMap {
+    /// Mode 2 radios with the default FS-i6X mixing: CH1 aileron, CH2 elevator,
+    /// CH3 throttle, CH4 rudder, CH5 VRA and CH6 VRB, then SWA to SWD on CH7 to
+    /// CH10 once the auxiliary channels are unlocked.
+    pub const MODE_2: ChannelMap = ChannelMap {
+        sources: [
+            ChannelSource::new(0),
+            ChannelSource::new(1),
+            ChannelSource::new(2),
+            ChannelSource::new(3),
+            ChannelSource::new(4),
+            ChannelSource::new(5),
+            ChannelSource::new(6),
+            ChannelSource::new(7),
+            ChannelSource::new(8),
+            ChannelSource::new(9),
+        ],
+    };
+
+    /// Returns the source of `function`.
+    pub fn source(&self, function: ChannelFunction) -> ChannelSource {
+        self.sources[function as usize]
+    }
+
+    /// Returns the value of each function, indexed by `ChannelFunction`, from the
+    /// receiver channels. A function whose channel the frame lacks reads at rest.
+    pub fn apply(&self, received: &RcChannels) -> [PositionValue; NUM_CHANNEL_FUNCTIONS] {
+        let mut values = [0; NUM_CHANNEL_FUNCTIONS];
+        for (function, value) in ChannelFunction::ALL.into_iter().zip(values.iter_mut()) {
+            let source = self.source(function);
+            *value = match received.get(source.channel as usize) {
+                Some(value) if source.reverse => (2 * MID_POSITION).saturating_sub(value),
+                Some(value) => value,
+                None => function.rest_value(),
+            };
+        }
+        values
+    }
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::MODE_2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_each_function_from_its_source() {
+        // Mode 1: the elevator is on the left stick and the throttle on the right one
+        let mut map = ChannelMap::MODE_2;
+        map.sources[ChannelFunction::Forward as usize].channel = 2;
+        map.sources[ChannelFunction::Throttle as usize].channel = 1;
+        map.sources[ChannelFunction::Turn as usize].reverse = true;
+
+        let received = RcChannels {
+            values: [1500, 1000, 1800, 1900, 1500, 2000, 2000, 1000, 1500, 1000],
+            count: 10,
+        };
+        assert_eq!(
+            map.apply(&received),
+            [1500, 1800, 1000, 1100, 1500, 2000, 2000, 1000, 1500, 1000]
+        );
+        assert_eq!(ChannelMap::MODE_2.apply(&received), received.values);
+    }
+
+    #[test]
+    fn missing_channels_read_at_rest() {
+        let received = RcChannels {
+            values: [1700, 1700, 1700, 1700, 1700, 1700, 2000, 2000, 2000, 2000],
+            count: 6,
+        };
+        let mut map = ChannelMap::MODE_2;
+        map.sources[ChannelFunction::Throttle as usize].channel = 9;
+        assert_eq!(
+            map.apply(&received),
+            [1700, 1700, 1000, 1700, 1700, 1700, 1000, 1000, 1000, 1000]
+        );
+    }
+
+    #[test]
+    fn names_every_function() {
+        for function in ChannelFunction::ALL {
+            assert_eq!(ChannelFunction::from_name(function.name()), Some(function));
+        }
+        assert_eq!(ChannelFunction::from_name("aux"), None);
+    }
+}
@@ -1,10 +1,12 @@
+pub mod channel_map;
+
 use super::ppm::PositionValue;
-use crate::robot::ppm::Ppm;
-use arduino_hal::Peripherals;
+use crate::hal::{FrameStats, RcInput, NUM_STICK_CHANNELS};
+use channel_map::{ChannelFunction, ChannelMap};
 
-// Stick positions middle range
-pub const RANGE_MID_POSITION_MAX: u16 = 1550;
-pub const RANGE_MID_POSITION_MIN: u16 = 1450;
+// Pulses a three position switch reads as its middle
+const SWITCH_MIDDLE_MAX: u16 = 1550;
+const SWITCH_MIDDLE_MIN: u16 = 1450;
 
 // Stick positions
 pub const MAX_POSITION: u16 = 2000;
@@ -12,12 +14,12 @@ pub const MIN_POSITION: u16 = 1000;
 pub const MID_POSITION: u16 = 1500;
 
 // Channels
-const CHANNEL_0: usize = 0;
-const CHANNEL_1: usize = 1;
-const CHANNEL_2: usize = 2;
-const CHANNEL_3: usize = 3;
-const CHANNEL_4: usize = 4;
-const CHANNEL_5: usize = 5;
+pub const CHANNEL_0: usize = 0;
+pub const CHANNEL_1: usize = 1;
+pub const CHANNEL_2: usize = 2;
+pub const CHANNEL_3: usize = 3;
+pub const CHANNEL_4: usize = 4;
+pub const CHANNEL_5: usize = 5;
 
 pub enum Stick {
     Right(StickMovement),
@@ -26,11 +28,125 @@ pub enum Stick {
     Vrb(StickMovement),
 }
 
+/// Position of a switch of the transmitter.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwitchPosition {
+    /// Away from the pilot, the shortest pulse.
+    Low,
+    Middle,
+    High,
+}
+
+/// A switch channel, typed by the number of positions of the switch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Switch {
+    /// Two position switch, SWA, SWB and SWD of the FS-i6X. Never `Middle`.
+    TwoWay(SwitchPosition),
+    /// Three position switch, SWC of the FS-i6X.
+    ThreeWay(SwitchPosition),
+}
+
+impl Switch {
+    /// Reads a two position switch, split at the middle of the range.
+    pub fn two_way(value: PositionValue) -> Self {
+        Switch::TwoWay(if value < MID_POSITION {
+            SwitchPosition::Low
+        } else {
+            SwitchPosition::High
+        })
+    }
+
+    /// Reads a three position switch, middle within 50 µs of the middle of the range.
+    pub fn three_way(value: PositionValue) -> Self {
+        Switch::ThreeWay(if value < SWITCH_MIDDLE_MIN {
+            SwitchPosition::Low
+        } else if value > SWITCH_MIDDLE_MAX {
+            SwitchPosition::High
+        } else {
+            SwitchPosition::Middle
+        })
+    }
+
+    /// Returns the position of the switch.
+    pub fn position(self) -> SwitchPosition {
+        match self {
+            Switch::TwoWay(position) | Switch::ThreeWay(position) => position,
+        }
+    }
+}
+
 pub struct FlySky {
     pub right: Stick,
     pub left: Stick,
     pub vra: Stick,
     pub vrb: Stick,
+    pub swa: Switch,
+    pub swb: Switch,
+    pub swc: Switch,
+    pub swd: Switch,
+    pub signal: Signal,
+    /// Value of each channel the sticks were built from, in the order of the
+    /// channel map.
+    pub channels: [PositionValue; NUM_STICK_CHANNELS],
+}
+
+/// Timing of the last complete frame received from the radio, and the quality
+/// of the link.
+pub struct Signal {
+    pub now_ms: u32,
+    pub last_frame_ms: Option<u32>,
+    /// True if the frame arrived since the previous status.
+    pub new_frame: bool,
+    pub frames: FrameStats,
+}
+
+impl Signal {
+    /// Returns the milliseconds since the last complete frame, if any arrived.
+    pub fn age_ms(&self) -> Option<u32> {
+        self.last_frame_ms
+            .map(|last_frame_ms| self.now_ms.wrapping_sub(last_frame_ms))
+    }
+
+    /// Returns true if a complete frame arrived within `timeout_ms`.
+    pub fn is_valid(&self, timeout_ms: u32) -> bool {
+        matches!(self.age_ms(), Some(age_ms) if age_ms <= timeout_ms)
+    }
+}
+
+impl FlySky {
+    /// Returns the switch read for `function`, if it is one of the switches.
+    pub fn switch(&self, function: ChannelFunction) -> Option<Switch> {
+        match function {
+            ChannelFunction::Swa => Some(self.swa),
+            ChannelFunction::Swb => Some(self.swb),
+            ChannelFunction::Swc => Some(self.swc),
+            ChannelFunction::Swd => Some(self.swd),
+            _ => None,
+        }
+    }
+
+    /// Returns true if all self-centering stick axes are at rest.
+    /// The left vertical axis is the throttle and is not checked.
+    pub fn sticks_centered(&self) -> bool {
+        let right = match &self.right {
+            Stick::Right(movement) => movement.is_centered(),
+            _ => false,
+        };
+        let left = match &self.left {
+            Stick::Left(movement) => matches!(movement.right_left, Position::Center(_)),
+            _ => false,
+        };
+        right && left
+    }
+
+    /// Returns true if the left vertical axis, the throttle, is inside the middle
+    /// range, the same check `sticks_centered` makes on the other axes.
+    pub fn left_vertical_centered(&self) -> bool {
+        match &self.left {
+            Stick::Left(movement) => matches!(movement.up_down, Position::Center(_)),
+            _ => false,
+        }
+    }
 }
 
 impl Stick {
@@ -95,6 +211,14 @@ pub struct StickMovement {
     pub center: Position,
 }
 
+impl StickMovement {
+    /// Returns true if both axes are inside the middle range.
+    pub fn is_centered(&self) -> bool {
+        matches!(self.up_down, Position::Center(_))
+            && matches!(self.right_left, Position::Center(_))
+    }
+}
+
 #[allow(dead_code)]
 pub enum Position {
     Up(PositionValue),
@@ -104,10 +228,17 @@ pub enum Position {
     Center(PositionValue),
 }
 
-pub enum FlySkyPpmPin {
-    D2,
-    #[allow(dead_code)]
-    D3,
+impl Position {
+    /// Returns the raw channel value of the position.
+    pub fn value(&self) -> PositionValue {
+        match self {
+            Position::Up(value)
+            | Position::Down(value)
+            | Position::Left(value)
+            | Position::Right(value)
+            | Position::Center(value) => *value,
+        }
+    }
 }
 
 impl Default for FlySky {
@@ -134,54 +265,103 @@ impl Default for FlySky {
                 up_down: Position::Center(MID_POSITION),
                 center: Position::Center(MID_POSITION),
             }),
+            swa: Switch::TwoWay(SwitchPosition::Low),
+            swb: Switch::TwoWay(SwitchPosition::Low),
+            swc: Switch::ThreeWay(SwitchPosition::Low),
+            swd: Switch::TwoWay(SwitchPosition::Low),
+            signal: Signal {
+                now_ms: 0,
+                last_frame_ms: None,
+                new_frame: false,
+                frames: FrameStats::default(),
+            },
+            channels: [
+                MID_POSITION,
+                MID_POSITION,
+                MIN_POSITION,
+                MID_POSITION,
+                MID_POSITION,
+                MID_POSITION,
+            ],
         }
     }
 }
 
 pub trait StickConverter {
-    fn to_flysky(self) -> FlySky;
+    /// Builds the sticks, an axis within `center_band` of the middle reading centered.
+    fn to_flysky(self, center_band: u16) -> FlySky;
 }
 
-pub struct FlySkyManager {
-    ppm: Ppm,
+/// Returns true if `value` is within `center_band` of the middle of the range.
+pub fn is_centered(value: PositionValue, center_band: u16) -> bool {
+    value.abs_diff(MID_POSITION) <= center_band
 }
 
-impl FlySkyManager {
-    /// Initializes the FlySkyManager with the given peripherals and PPM pin.
-    pub fn init(peripherals: &Peripherals, flysky_ppm_pin: FlySkyPpmPin) -> Self {
-        let current_ppm = match flysky_ppm_pin {
-            FlySkyPpmPin::D2 => Ppm::init_from_d2(peripherals),
-            FlySkyPpmPin::D3 => Ppm::init_from_d3(peripherals),
-        };
-        FlySkyManager { ppm: current_ppm }
+pub struct FlySkyManager<R: RcInput> {
+    input: R,
+    // Sequence number of the frame of the previous status
+    last_sequence: Option<u16>,
+}
+
+impl<R: RcInput> FlySkyManager<R> {
+    /// Creates a FlySkyManager reading channels from the given receiver input.
+    pub fn new(input: R) -> Self {
+        FlySkyManager {
+            input,
+            last_sequence: None,
+        }
     }
 
-    /// Returns the current FlySky status by converting PPM channels to stick positions.
-    pub fn get_status(&self) -> FlySky {
-        self.ppm.get_channels().to_flysky()
+    /// Returns the current FlySky status by converting the channels of the last
+    /// frame, read through `map`, to stick positions and switches, along with the
+    /// age of the frame at `now_ms` and the frame counts of the receiver. Sticks
+    /// within `center_band` of the middle read centered.
+    pub fn get_status(&mut self, now_ms: u32, map: &ChannelMap, center_band: u16) -> FlySky {
+        let frame = self.input.get_frame();
+        let sequence = frame.map(|frame| frame.sequence);
+        let new_frame = sequence.is_some() && sequence != self.last_sequence;
+        self.last_sequence = sequence;
+        let functions = map.apply(&frame.map(|frame| frame.channels).unwrap_or_default());
+        let mut channels = [0; NUM_STICK_CHANNELS];
+        channels.copy_from_slice(&functions[..NUM_STICK_CHANNELS]);
+        let mut status = channels.into_iter().enumerate().to_flysky(center_band);
+        status.channels = channels;
+        status.swa = Switch::two_way(functions[ChannelFunction::Swa as usize]);
+        status.swb = Switch::two_way(functions[ChannelFunction::Swb as usize]);
+        status.swc = Switch::three_way(functions[ChannelFunction::Swc as usize]);
+        status.swd = Switch::two_way(functions[ChannelFunction::Swd as usize]);
+        status.signal = Signal {
+            now_ms,
+            last_frame_ms: frame.map(|frame| frame.received_ms),
+            new_frame,
+            frames: self.input.get_frame_stats(),
+        };
+        status
     }
 }
 
 impl StickConverter for core::iter::Enumerate<core::array::IntoIter<PositionValue, 6>> {
-    /// Converts an iterator of PPM channel values to a FlySky status struct.
-    fn to_flysky(self) -> FlySky {
+    /// Converts an iterator of PPM or iBUS channel values to a FlySky status struct.
+    fn to_flysky(self, center_band: u16) -> FlySky {
         let mut status = FlySky::default();
+        let range_mid_position_max = MID_POSITION + center_band;
+        let range_mid_position_min = MID_POSITION - center_band;
 
         for (id_channel, value) in self.into_iter() {
             match id_channel {
                 CHANNEL_0 => {
-                    if value > RANGE_MID_POSITION_MAX && value <= MAX_POSITION {
+                    if value > range_mid_position_max && value <= MAX_POSITION {
                         status.right.set_right_value(value);
-                    } else if value < RANGE_MID_POSITION_MIN && value >= MIN_POSITION {
+                    } else if (MIN_POSITION..range_mid_position_min).contains(&value) {
                         status.right.set_left_value(value);
                     } else {
                         status.right.set_center_value(value);
                     }
                 }
                 CHANNEL_1 => {
-                    if value > RANGE_MID_POSITION_MAX {
+                    if value > range_mid_position_max {
                         status.right.set_up_value(value);
-                    } else if value < RANGE_MID_POSITION_MIN {
+                    } else if value < range_mid_position_min {
                         status.right.set_down_value(value);
                     } else {
                         status.right.set_center_value(value);
@@ -190,34 +370,34 @@ impl StickConverter for core::iter::Enumerate<core::array::IntoIter<PositionValu
                 CHANNEL_2 => {
                     if value > MID_POSITION && value <= MAX_POSITION {
                         status.left.set_up_value(value);
-                    } else if value < MID_POSITION && value >= MIN_POSITION {
+                    } else if (MIN_POSITION..MID_POSITION).contains(&value) {
                         status.left.set_down_value(value);
                     } else {
                         status.left.set_center_value(value);
                     }
                 }
                 CHANNEL_3 => {
-                    if value > RANGE_MID_POSITION_MAX {
+                    if value > range_mid_position_max {
                         status.left.set_right_value(value);
-                    } else if value < RANGE_MID_POSITION_MIN {
+                    } else if value < range_mid_position_min {
                         status.left.set_left_value(value);
                     } else {
                         status.left.set_center_value(value);
                     }
                 }
                 CHANNEL_4 => {
-                    if value > RANGE_MID_POSITION_MAX {
+                    if value > range_mid_position_max {
                         status.vra.set_right_value(value);
-                    } else if value < RANGE_MID_POSITION_MIN {
+                    } else if value < range_mid_position_min {
                         status.vra.set_left_value(value);
                     } else {
                         status.vra.set_center_value(value);
                     }
                 }
                 CHANNEL_5 => {
-                    if value > RANGE_MID_POSITION_MAX {
+                    if value > range_mid_position_max {
                         status.vrb.set_right_value(value);
-                    } else if value < RANGE_MID_POSITION_MIN {
+                    } else if value < range_mid_position_min {
                         status.vrb.set_left_value(value);
                     } else {
                         status.vrb.set_center_value(value);
@@ -229,3 +409,60 @@ impl StickConverter for core::iter::Enumerate<core::array::IntoIter<PositionValu
         status
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockRcInput;
+
+    #[test]
+    fn reads_switches_of_ten_channel_frames() {
+        let input = MockRcInput::new();
+        let mut manager = FlySkyManager::new(input.clone());
+        input.receive_frame(
+            &[1500, 1500, 1000, 1500, 1500, 1500, 2000, 1000, 1500, 1996],
+            0,
+        );
+        let status = manager.get_status(0, &ChannelMap::MODE_2, 50);
+        assert_eq!(status.swa, Switch::TwoWay(SwitchPosition::High));
+        assert_eq!(status.swb, Switch::TwoWay(SwitchPosition::Low));
+        assert_eq!(status.swc, Switch::ThreeWay(SwitchPosition::Middle));
+        assert_eq!(status.swd.position(), SwitchPosition::High);
+
+        // Six channel frames leave the switches off
+        input.receive_frame(&[1500, 1500, 1000, 1500, 1500, 1500], 20);
+        let status = manager.get_status(20, &ChannelMap::MODE_2, 50);
+        assert_eq!(status.swc, Switch::ThreeWay(SwitchPosition::Low));
+    }
+
+    #[test]
+    fn tells_new_frames_apart() {
+        let input = MockRcInput::new();
+        let mut manager = FlySkyManager::new(input.clone());
+        assert!(
+            !manager
+                .get_status(0, &ChannelMap::MODE_2, 50)
+                .signal
+                .new_frame
+        );
+
+        input.receive_frame(&[1500, 1500, 1000, 1500, 1500, 1500], 20);
+        let status = manager.get_status(20, &ChannelMap::MODE_2, 50);
+        assert!(status.signal.new_frame);
+        assert_eq!(status.signal.last_frame_ms, Some(20));
+        assert!(
+            !manager
+                .get_status(30, &ChannelMap::MODE_2, 50)
+                .signal
+                .new_frame
+        );
+
+        input.receive_frame(&[1500, 1500, 1000, 1500, 1500, 1500], 40);
+        assert!(
+            manager
+                .get_status(40, &ChannelMap::MODE_2, 50)
+                .signal
+                .new_frame
+        );
+    }
+}
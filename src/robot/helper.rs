@@ -1,14 +1,11 @@
-use crate::robot::{
-    flysky::{MAX_POSITION, MIN_POSITION, RANGE_MID_POSITION_MAX, RANGE_MID_POSITION_MIN},
-    ppm::PositionValue,
-};
+use crate::robot::{calibration::ChannelCalibration, ppm::PositionValue};
 pub const MAX_POTENCY: u8 = 255;
 pub const MIN_POTENCY: u8 = 0;
-/// Maps a PositionValue (u16) from the FlySky PPM range to a potency value (u8).
+/// Maps a PositionValue (u16) from the calibrated range of the channel to a potency value (u8).
 /// Ensures the value is clamped to the expected range before scaling.
-pub fn map_value_to_potency(value: PositionValue) -> u8 {
-    let in_min: u16 = MIN_POSITION;
-    let in_max: u16 = MAX_POSITION;
+pub fn map_value_to_potency(value: PositionValue, calibration: &ChannelCalibration) -> u8 {
+    let in_min: u16 = calibration.min;
+    let in_max: u16 = calibration.max;
     let out_min: u16 = MIN_POTENCY as u16;
     let out_max: u16 = MAX_POTENCY as u16;
 
@@ -21,56 +18,80 @@ pub fn map_value_to_potency(value: PositionValue) -> u8 {
     scaled as u8
 }
 
-/// Maps a u16 value from the FlySky PPM range to a u8 value, applying a central deadzone.
-/// Returns 0 for values in the deadzone, otherwise scales to 0-255.
-pub fn map_u16_to_u8_2(value: u16) -> u8 {
-    let clamped = value.clamp(MIN_POSITION, MAX_POSITION);
-    let mid = (MIN_POSITION + MAX_POSITION) / 2;
-
-    if clamped < mid {
-        // Tramo izquierdo: MIN_POSITION -> 255, MID_POSITION -> 0
-        let scaled = (mid - clamped) as u32 * MAX_POTENCY as u32 / (mid - MIN_POSITION) as u32;
-        scaled as u8
-    } else {
-        // Tramo derecho: MID_POSITION -> 0, MAX_POSITION -> 255
-        let scaled = (clamped - mid) as u32 * MAX_POTENCY as u32 / (MAX_POSITION - mid) as u32;
-        scaled as u8
-    }
-}
-pub fn map_u16_to_i16(value: u16, _prev: u8) -> i16 {
-    let center = 1500.0;
-    let deadzone = 50.0; // zona muerta para evitar vibraciones
-    let val = value as f32;
+/// Maps a u16 value from the calibrated range of the channel to a signed potency in
+/// -255..=255, returning 0 inside the deadzone (in microseconds) around its center.
+/// Integer only, the ATmega328p has no FPU.
+pub fn map_u16_to_i16(value: u16, deadzone: u16, calibration: &ChannelCalibration) -> i16 {
+    let center = calibration.center as i32;
+    let deadzone = deadzone as i32; // zona muerta para evitar vibraciones
+    let val = value.clamp(calibration.min, calibration.max) as i32;
+    let full_scale = MAX_POTENCY as i32;
 
     let mapped = if val > center + deadzone {
         // Adelante o derecha
-        ((val - (center + deadzone)) / (2000.0 - (center + deadzone)) * 255.0) as i16
+        (val - (center + deadzone)) * full_scale / (calibration.max as i32 - (center + deadzone))
     } else if val < center - deadzone {
         // Atrás o izquierda
-        -(((center - deadzone) - val) / ((center - deadzone) - 1000.0) * 255.0) as i16
+        -(((center - deadzone) - val) * full_scale / ((center - deadzone) - calibration.min as i32))
     } else {
         0 // Dentro de la zona muerta
     };
 
-    mapped
+    mapped as i16
 }
-pub fn map_u16_to_u8(value: u16, last: u8) -> u8 {
-    let clamped = value.clamp(MIN_POSITION, MAX_POSITION);
-    let mid = (MIN_POSITION + MAX_POSITION) / 2;
 
-    let mut scaled = if clamped < mid {
-        // Tramo izquierdo: MIN_POSITION -> 255, MID -> 0
-        (mid - clamped) as u32 * MAX_POTENCY as u32 / (mid - MIN_POSITION) as u32
-    } else {
-        // Tramo derecho: MID -> 0, MAX -> 255
-        (clamped - mid) as u32 * MAX_POTENCY as u32 / (MAX_POSITION - mid) as u32
-    } as u8;
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Floating point mapping the integer version must reproduce.
+    pub(crate) fn map_u16_to_i16_reference(
+        value: u16,
+        deadzone: u16,
+        calibration: &ChannelCalibration,
+    ) -> i16 {
+        let center = calibration.center as f32;
+        let (min, max) = (calibration.min as f32, calibration.max as f32);
+        let deadzone = deadzone as f32;
+        let val = value.clamp(calibration.min, calibration.max) as f32;
 
-    // ---- Histeresis: solo cambia si la diferencia es significativa ----
-    let threshold = 2; // tolerancia (ajustable)
-    if scaled.abs_diff(last) <= threshold {
-        scaled = last;
+        if val > center + deadzone {
+            ((val - (center + deadzone)) / (max - (center + deadzone)) * 255.0) as i16
+        } else if val < center - deadzone {
+            -(((center - deadzone) - val) / ((center - deadzone) - min) * 255.0) as i16
+        } else {
+            0
+        }
     }
 
-    scaled
+    /// The travel an i6X actually reads, off center.
+    const OFF_CENTER: ChannelCalibration = ChannelCalibration {
+        min: 988,
+        center: 1516,
+        max: 2012,
+    };
+
+    #[test]
+    fn integer_mapping_matches_float_reference() {
+        for calibration in [ChannelCalibration::default(), OFF_CENTER] {
+            for deadzone in [0, 10, 50, 100, 250, 499, 500, 600] {
+                for value in 900..=2100 {
+                    let mapped = map_u16_to_i16(value, deadzone, &calibration);
+                    let reference = map_u16_to_i16_reference(value, deadzone, &calibration);
+                    assert!(
+                        mapped.abs_diff(reference) <= 1,
+                        "value {value} deadzone {deadzone}: {mapped} != {reference}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn calibrated_ends_reach_full_scale() {
+        assert_eq!(map_u16_to_i16(1516, 0, &OFF_CENTER), 0);
+        assert_eq!(map_u16_to_i16(2012, 0, &OFF_CENTER), 255);
+        assert_eq!(map_u16_to_i16(988, 0, &OFF_CENTER), -255);
+        assert_eq!(map_value_to_potency(2012, &OFF_CENTER), 255);
+    }
 }
@@ -0,0 +1,180 @@
+use crate::hal::FrameStats;
+use crate::robot::ppm::PositionValue;
+
+const IBUS_FRAME_LENGTH: u8 = 0x20;
+const IBUS_COMMAND: u8 = 0x40;
+const IBUS_HEADER_SIZE: usize = 2;
+const IBUS_CHECKSUM_SIZE: usize = 2;
+const IBUS_CHANNEL_MASK: u16 = 0x0FFF;
+pub const IBUS_NUM_CHANNELS: usize = 14;
+
+/// Reassembles FlySky iBUS frames (32 bytes at 115200 baud) one byte at a time.
+/// A frame is `0x20 0x40`, 14 little endian channels and a little endian checksum
+/// equal to `0xFFFF` minus the sum of all previous bytes. Frames with a bad
+/// checksum are counted as bad, missed frames are not counted.
+pub struct IbusDecoder {
+    buffer: [u8; IBUS_FRAME_LENGTH as usize],
+    index: usize,
+    stats: FrameStats,
+}
+
+impl IbusDecoder {
+    /// Creates a decoder waiting for the start of a frame.
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; IBUS_FRAME_LENGTH as usize],
+            index: 0,
+            stats: FrameStats {
+                good: 0,
+                bad: 0,
+                missed: 0,
+            },
+        }
+    }
+
+    /// Feeds a received byte. Returns the channel values when it completes a frame
+    /// with a valid checksum.
+    pub fn push_byte(&mut self, byte: u8) -> Option<[PositionValue; IBUS_NUM_CHANNELS]> {
+        match self.index {
+            0 if byte != IBUS_FRAME_LENGTH => return None,
+            1 if byte != IBUS_COMMAND => {
+                // The byte may be the length of a frame starting right here
+                self.index = if byte == IBUS_FRAME_LENGTH { 1 } else { 0 };
+                return None;
+            }
+            _ => {}
+        }
+
+        self.buffer[self.index] = byte;
+        self.index += 1;
+        if self.index < self.buffer.len() {
+            return None;
+        }
+
+        self.index = 0;
+        if self.is_checksum_valid() {
+            self.stats.good = self.stats.good.wrapping_add(1);
+            Some(self.channels())
+        } else {
+            self.stats.bad = self.stats.bad.wrapping_add(1);
+            None
+        }
+    }
+
+    /// Returns the counts of good and bad frames since start.
+    pub fn get_stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Returns true if the checksum at the end of the buffer matches its content.
+    fn is_checksum_valid(&self) -> bool {
+        let payload_end = self.buffer.len() - IBUS_CHECKSUM_SIZE;
+        let sum = self.buffer[..payload_end]
+            .iter()
+            .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+        let checksum = read_u16(&self.buffer, payload_end);
+        checksum == 0xFFFF - sum
+    }
+
+    /// Extracts the channel values from the buffer.
+    fn channels(&self) -> [PositionValue; IBUS_NUM_CHANNELS] {
+        let mut channels = [0; IBUS_NUM_CHANNELS];
+        for (id_channel, value) in channels.iter_mut().enumerate() {
+            *value = read_u16(&self.buffer, IBUS_HEADER_SIZE + id_channel * 2) & IBUS_CHANNEL_MASK;
+        }
+        channels
+    }
+}
+
+impl Default for IbusDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads a little endian u16 at the given offset.
+fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the frame a receiver sends for `channels`.
+    fn frame(channels: [PositionValue; IBUS_NUM_CHANNELS]) -> [u8; IBUS_FRAME_LENGTH as usize] {
+        let mut frame = [0; IBUS_FRAME_LENGTH as usize];
+        frame[0] = IBUS_FRAME_LENGTH;
+        frame[1] = IBUS_COMMAND;
+        for (id_channel, value) in channels.iter().enumerate() {
+            let offset = IBUS_HEADER_SIZE + id_channel * 2;
+            frame[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        let payload_end = frame.len() - IBUS_CHECKSUM_SIZE;
+        let sum = frame[..payload_end]
+            .iter()
+            .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+        frame[payload_end..].copy_from_slice(&(0xFFFF - sum).to_le_bytes());
+        frame
+    }
+
+    fn push_all(
+        decoder: &mut IbusDecoder,
+        bytes: &[u8],
+    ) -> std::vec::Vec<[PositionValue; IBUS_NUM_CHANNELS]> {
+        bytes
+            .iter()
+            .filter_map(|byte| decoder.push_byte(*byte))
+            .collect()
+    }
+
+    const CHANNELS: [PositionValue; IBUS_NUM_CHANNELS] = [
+        1500, 1520, 1000, 1480, 2000, 1500, 1000, 2000, 1500, 1000, 1500, 1500, 1500, 1500,
+    ];
+
+    #[test]
+    fn decodes_valid_frames() {
+        let mut decoder = IbusDecoder::new();
+        assert_eq!(push_all(&mut decoder, &frame(CHANNELS)), [CHANNELS]);
+        assert_eq!(push_all(&mut decoder, &frame(CHANNELS)), [CHANNELS]);
+        assert_eq!(decoder.get_stats().good, 2);
+        assert_eq!(decoder.get_stats().bad, 0);
+    }
+
+    #[test]
+    fn drops_frames_with_a_bad_checksum() {
+        let mut decoder = IbusDecoder::new();
+        let mut corrupted = frame(CHANNELS);
+        corrupted[5] ^= 0x01;
+        assert!(push_all(&mut decoder, &corrupted).is_empty());
+        // A checksum equal to the sum itself, instead of 0xFFFF minus it
+        let mut summed = frame(CHANNELS);
+        let payload_end = summed.len() - IBUS_CHECKSUM_SIZE;
+        let sum = 0xFFFF - read_u16(&summed, payload_end);
+        summed[payload_end..].copy_from_slice(&sum.to_le_bytes());
+        assert!(push_all(&mut decoder, &summed).is_empty());
+        assert_eq!(decoder.get_stats().bad, 2);
+
+        assert_eq!(push_all(&mut decoder, &frame(CHANNELS)), [CHANNELS]);
+        assert_eq!(decoder.get_stats().good, 1);
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        let mut decoder = IbusDecoder::new();
+        // Noise, then a stray length byte right before the frame
+        let mut bytes = std::vec![0x00, 0x40, 0x13, 0x20, 0x55, IBUS_FRAME_LENGTH];
+        bytes.extend_from_slice(&frame(CHANNELS));
+        // A frame cut short, then a whole one
+        bytes.extend_from_slice(&frame(CHANNELS)[..12]);
+        assert_eq!(push_all(&mut decoder, &bytes), [CHANNELS]);
+        let stats = decoder.get_stats();
+        assert_eq!((stats.good, stats.bad), (1, 0));
+
+        // The cut frame swallows the start of the next one, which is then lost
+        assert!(push_all(&mut decoder, &frame(CHANNELS)).is_empty());
+        assert_eq!(decoder.get_stats().bad, 1);
+        assert_eq!(push_all(&mut decoder, &frame(CHANNELS)), [CHANNELS]);
+        assert_eq!(decoder.get_stats().good, 2);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::robot::flysky::{Switch, SwitchPosition};
+use crate::robot::helper::MAX_POTENCY;
+use crate::robot::mecanum::{self, DriveCommand, WheelOutputs};
+
+/// How the wheel duties are computed from the sticks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kinematics {
+    /// Four mecanum wheels: the right stick translates in any direction and the
+    /// left stick turns.
+    Mecanum,
+    /// Skid steer: the right stick drives forward and backward and the left stick
+    /// turns. The lateral axis is ignored.
+    Arcade,
+    /// Skid steer: the left stick drives the left side and the right stick the
+    /// right side. It only drives once the left stick was seen centered, since a
+    /// throttle stick resting down would drive the left side backward.
+    Tank,
+}
+
+impl Kinematics {
+    /// Returns the name reported on serial.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kinematics::Mecanum => "mecanum",
+            Kinematics::Arcade => "arcade",
+            Kinematics::Tank => "tank",
+        }
+    }
+
+    /// Mixes `command` into the four wheel outputs, limited to `percent` of the
+    /// speed before they are normalized.
+    pub fn mix(self, command: DriveCommand, percent: u8) -> WheelOutputs {
+        match self {
+            Kinematics::Mecanum => mecanum::drive(command, percent),
+            Kinematics::Arcade | Kinematics::Tank => differential(command, percent),
+        }
+    }
+}
+
+/// How the kinematics are chosen.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DriveMode {
+    Fixed(Kinematics),
+    /// Chosen by the three position switch SWC: up mecanum, middle arcade and
+    /// down tank.
+    Switch,
+}
+
+impl DriveMode {
+    /// Returns the kinematics to drive with, given the position of SWC.
+    pub fn select(self, swc: Switch) -> Kinematics {
+        match self {
+            DriveMode::Fixed(kinematics) => kinematics,
+            DriveMode::Switch => match swc.position() {
+                SwitchPosition::Low => Kinematics::Mecanum,
+                SwitchPosition::Middle => Kinematics::Arcade,
+                SwitchPosition::High => Kinematics::Tank,
+            },
+        }
+    }
+
+    /// Returns the number of the mode in the shell and the saved settings.
+    pub fn code(self) -> u8 {
+        match self {
+            DriveMode::Fixed(Kinematics::Mecanum) => 0,
+            DriveMode::Fixed(Kinematics::Arcade) => 1,
+            DriveMode::Fixed(Kinematics::Tank) => 2,
+            DriveMode::Switch => 3,
+        }
+    }
+
+    /// Returns the mode with the given number, if any.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(DriveMode::Fixed(Kinematics::Mecanum)),
+            1 => Some(DriveMode::Fixed(Kinematics::Arcade)),
+            2 => Some(DriveMode::Fixed(Kinematics::Tank)),
+            3 => Some(DriveMode::Switch),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the command driving the left side at `left` and the right side at
+/// `right`, as forward speed and rotation. Odd sums are halved away from zero, so
+/// a side at full scale stays at full scale once the mix is normalized.
+pub fn tank(left: i16, right: i16) -> DriveCommand {
+    let half = |value: i16| (value + value.signum()) / 2;
+    DriveCommand {
+        vx: 0,
+        vy: half(left + right),
+        omega: half(left - right),
+    }
+}
+
+/// Mixes the forward speed and rotation of `command` into both sides of a skid
+/// steer chassis, wheels A and C on the left and B and D on the right, limited to
+/// `percent` of the speed. Both sides are scaled by the same factor when one
+/// exceeds full scale.
+fn differential(command: DriveCommand, percent: u8) -> WheelOutputs {
+    let percent = percent.min(100) as i32;
+    let mut left = (command.vy as i32 + command.omega as i32) * percent / 100;
+    let mut right = (command.vy as i32 - command.omega as i32) * percent / 100;
+
+    let full_scale = MAX_POTENCY as i32;
+    let max = left.abs().max(right.abs());
+    if max > full_scale {
+        left = left * full_scale / max;
+        right = right * full_scale / max;
+    }
+
+    let (left, right) = (left as i16, right as i16);
+    WheelOutputs {
+        a: left,
+        b: right,
+        c: left,
+        d: right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sides(wheels: WheelOutputs) -> [i16; 4] {
+        [wheels.a, wheels.b, wheels.c, wheels.d]
+    }
+
+    #[test]
+    fn arcade_ignores_the_lateral_axis() {
+        let command = DriveCommand {
+            vx: 200,
+            vy: 100,
+            omega: 50,
+        };
+        assert_eq!(
+            sides(Kinematics::Arcade.mix(command, 100)),
+            [150, 50, 150, 50]
+        );
+
+        // Full forward and full turn keep the ratio between both sides
+        let command = DriveCommand {
+            vx: 0,
+            vy: 255,
+            omega: 255,
+        };
+        assert_eq!(
+            sides(Kinematics::Arcade.mix(command, 100)),
+            [255, 0, 255, 0]
+        );
+    }
+
+    #[test]
+    fn tank_drives_each_side_from_its_stick() {
+        for (left, right) in [(255, 255), (255, -255), (-120, 40), (0, 200)] {
+            let wheels = Kinematics::Tank.mix(tank(left, right), 100);
+            assert_eq!(sides(wheels), [left, right, left, right]);
+        }
+        // Odd sums are off by one step at most, but still reach full scale
+        let wheels = Kinematics::Tank.mix(tank(255, 0), 100);
+        assert_eq!(sides(wheels), [255, 0, 255, 0]);
+        let wheels = Kinematics::Tank.mix(tank(-101, 40), 100);
+        assert!(sides(wheels)
+            .into_iter()
+            .zip([-101, 40, -101, 40])
+            .all(|(wheel, side)| wheel.abs_diff(side) <= 1));
+    }
+
+    #[test]
+    fn switch_selects_the_kinematics() {
+        let mode = DriveMode::Switch;
+        assert_eq!(
+            mode.select(Switch::ThreeWay(SwitchPosition::Low)),
+            Kinematics::Mecanum
+        );
+        assert_eq!(
+            mode.select(Switch::ThreeWay(SwitchPosition::Middle)),
+            Kinematics::Arcade
+        );
+        assert_eq!(
+            mode.select(Switch::ThreeWay(SwitchPosition::High)),
+            Kinematics::Tank
+        );
+        let fixed = DriveMode::Fixed(Kinematics::Arcade);
+        assert_eq!(
+            fixed.select(Switch::ThreeWay(SwitchPosition::High)),
+            Kinematics::Arcade
+        );
+
+        for code in 0..=3 {
+            assert_eq!(DriveMode::from_code(code).map(DriveMode::code), Some(code));
+        }
+        assert_eq!(DriveMode::from_code(4), None);
+    }
+}
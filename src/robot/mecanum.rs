@@ -0,0 +1,147 @@
+use crate::robot::helper::MAX_POTENCY;
+
+/// Velocity requested from the sticks, each axis in -255..=255.
+#[derive(Default, Clone, Copy)]
+pub struct DriveCommand {
+    /// Lateral speed, positive to the right.
+    pub vx: i16,
+    /// Forward speed, positive forward.
+    pub vy: i16,
+    /// Rotation speed, positive clockwise.
+    pub omega: i16,
+}
+
+/// Signed duty of each wheel, positive forward and negative backward.
+#[derive(Default, Clone, Copy)]
+pub struct WheelOutputs {
+    /// Front left.
+    pub a: i16,
+    /// Front right.
+    pub b: i16,
+    /// Rear left.
+    pub c: i16,
+    /// Rear right.
+    pub d: i16,
+}
+
+impl WheelOutputs {
+    /// Returns the outputs scaled down to `percent` of their value.
+    pub fn scaled(self, percent: u8) -> Self {
+        let percent = percent.min(100) as i32;
+        let scale = |value: i16| (value as i32 * percent / 100) as i16;
+        Self {
+            a: scale(self.a),
+            b: scale(self.b),
+            c: scale(self.c),
+            d: scale(self.d),
+        }
+    }
+}
+
+/// Mixes the translation and rotation of `command` into the four wheel outputs,
+/// limited to `percent` of the speed. When a wheel exceeds full scale all of them
+/// are scaled by the same factor, so the robot keeps the requested direction and
+/// spin ratio.
+pub fn drive(command: DriveCommand, percent: u8) -> WheelOutputs {
+    let DriveCommand { vx, vy, omega } = command;
+    let (vx, vy, omega) = (vx as i32, vy as i32, omega as i32);
+
+    // The limit applies before the normalization, so a combined move keeps the
+    // share of each wheel
+    let percent = percent.min(100) as i32;
+    let mut a = (vy + vx + omega) * percent / 100;
+    let mut b = (vy - vx - omega) * percent / 100;
+    let mut c = (vy - vx + omega) * percent / 100;
+    let mut d = (vy + vx - omega) * percent / 100;
+
+    // Normalize values from 0–255, integer only as the ATmega328p has no FPU
+    let full_scale = MAX_POTENCY as i32;
+    let max = a.abs().max(b.abs().max(c.abs().max(d.abs())));
+    if max > full_scale {
+        a = a * full_scale / max;
+        b = b * full_scale / max;
+        c = c * full_scale / max;
+        d = d * full_scale / max;
+    }
+
+    WheelOutputs {
+        a: a as i16,
+        b: b as i16,
+        c: c as i16,
+        d: d as i16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Floating point mix the integer version must reproduce.
+    fn drive_reference(vx: i16, vy: i16, omega: i16) -> [i16; 4] {
+        let mut wheels = [
+            vy + vx + omega,
+            vy - vx - omega,
+            vy - vx + omega,
+            vy + vx - omega,
+        ];
+        let max = wheels.iter().map(|wheel| wheel.abs()).max().unwrap() as f32;
+        if max > 255.0 {
+            let scale = 255.0 / max;
+            for wheel in wheels.iter_mut() {
+                *wheel = (*wheel as f32 * scale) as i16;
+            }
+        }
+        wheels
+    }
+
+    #[test]
+    fn integer_mix_matches_float_reference() {
+        for vx in (-255..=255).step_by(5) {
+            for vy in (-255..=255).step_by(5) {
+                for omega in (-255..=255).step_by(5) {
+                    let WheelOutputs { a, b, c, d } = drive(DriveCommand { vx, vy, omega }, 100);
+                    let reference = drive_reference(vx, vy, omega);
+                    for (wheel, expected) in [a, b, c, d].into_iter().zip(reference) {
+                        assert!(
+                            wheel.abs_diff(expected) <= 1,
+                            "vx {vx} vy {vy} omega {omega}: {wheel} != {expected}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normalization_keeps_the_ratio_between_wheels() {
+        let WheelOutputs { a, b, c, d } = drive(
+            DriveCommand {
+                vx: 255,
+                vy: 255,
+                omega: 255,
+            },
+            100,
+        );
+        assert_eq!([a, b, c, d], [255, -85, 85, 85]);
+    }
+
+    #[test]
+    fn speed_limit_applies_before_normalization() {
+        let forward = DriveCommand {
+            vx: 0,
+            vy: 255,
+            omega: 0,
+        };
+        let WheelOutputs { a, b, c, d } = drive(forward, 40);
+        assert_eq!([a, b, c, d], [102, 102, 102, 102]);
+
+        // Only the wheels beyond full scale once limited are normalized
+        let diagonal = DriveCommand {
+            vx: 255,
+            vy: 255,
+            omega: 0,
+        };
+        let WheelOutputs { a, b, c, d } = drive(diagonal, 60);
+        assert_eq!([a, b, c, d], [255, 0, 0, 255]);
+    }
+}
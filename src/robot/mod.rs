@@ -1,303 +1,898 @@
-mod flysky;
-mod helper;
-mod ppm;
-
-use crate::robot::flysky::{FlySkyManager, Position, StickMovement};
-use arduino_hal::{
-    default_serial,
-    hal::port::{PB0, PB1, PB2, PB3, PB4, PB5, PC0, PD0, PD1, PD3, PD4, PD5, PD6, PD7},
-    pac::{TC0, TC2},
-    pins,
-    port::{
-        mode::{self},
-        Pin,
-    },
-    prelude::*,
-    simple_pwm::{IntoPwmPin, Timer0Pwm, Timer2Pwm},
-    Peripherals, Usart,
+pub mod arming;
+pub mod battery;
+pub mod calibration;
+pub mod config;
+pub mod deadband;
+pub mod failsafe;
+pub mod flysky;
+pub mod helper;
+pub mod ibus;
+pub mod kinematics;
+pub mod mecanum;
+pub mod ppm;
+pub mod ramp;
+pub mod shaping;
+pub mod shell;
+pub mod speed;
+pub mod stopping;
+pub mod store;
+pub mod timing;
+pub mod wheels;
+
+use crate::hal::{
+    BatterySensor, Clock, ConfigStorage, FrameStats, Hardware, Motor, MotorId, Motors, RcInput,
+    SerialPort, StatusLed, UnwrapInfallible, NUM_STICK_CHANNELS,
+};
+use crate::robot::arming::{ArmMode, Arming, ArmingState};
+use crate::robot::battery::{BatteryMonitor, BatteryState};
+use crate::robot::calibration::{CalibrationGesture, CalibrationStep, Calibrator};
+use crate::robot::config::Config;
+use crate::robot::deadband::MAX_MIN_DUTY;
+use crate::robot::failsafe::Failsafe;
+use crate::robot::flysky::{
+    FlySky, FlySkyManager, StickMovement, CHANNEL_0, CHANNEL_1, CHANNEL_2, CHANNEL_3, CHANNEL_4,
+    MID_POSITION,
 };
+use crate::robot::kinematics::Kinematics;
+use crate::robot::mecanum::{DriveCommand, WheelOutputs};
+use crate::robot::ppm::PositionValue;
+use crate::robot::ramp::WheelRamp;
+use crate::robot::shell::{Command, LineBuffer, Setting};
+use crate::robot::speed::Gear;
+use crate::robot::stopping::{StopStyle, StopTimer};
+use crate::robot::timing::LoopStats;
+use crate::robot::wheels::Wheel;
+use crate::telemetry::{self, Telemetry};
 use flysky::Stick;
 
+// Time a motor runs for `motors test`, and each wheel for `wheels test`
+const MOTOR_TEST_MS: u32 = 1000;
+// Duty added every step of `motors start`, which reaches the largest min duty in 8s
+const MIN_DUTY_STEP: i16 = 5;
+const MIN_DUTY_STEP_MS: u32 = 200;
+// Half period of the LED blinking while the sticks are calibrated
+const CALIBRATION_BLINK_MS: u32 = 250;
+// Most received bytes handled per control step, so the shell never stalls the loop
+const MAX_SHELL_BYTES_PER_STEP: usize = 32;
+
+/// What a test from the serial shell runs.
+#[derive(Clone, Copy)]
+enum TestTarget {
+    Motor(MotorId),
+    /// The wheel at this index of `Wheel::ALL`, followed by the next ones.
+    Wheels(usize),
+    /// A motor sped up step by step until the pilot sees it turn.
+    MinDuty(MotorId),
+}
+
+/// Motor or wheels run from the serial shell while the robot is disarmed.
+#[derive(Clone, Copy)]
+struct MotorTest {
+    target: TestTarget,
+    duty: i16,
+    until_ms: u32,
+}
+
 trait StickProcessor {
     /// Processes stick input and updates the robot state.
-    fn process(self, robot: &mut Robot);
+    fn process<
+        M: Motors,
+        R: RcInput,
+        C: Clock,
+        S: SerialPort,
+        L: StatusLed,
+        B: BatterySensor,
+        E: ConfigStorage,
+    >(
+        self,
+        robot: &mut Robot<M, R, C, S, L, B, E>,
+    );
 }
 
 impl StickProcessor for Stick {
     /// Implements stick input processing for the robot.
-    fn process(self, robot: &mut Robot) {
+    fn process<
+        M: Motors,
+        R: RcInput,
+        C: Clock,
+        S: SerialPort,
+        L: StatusLed,
+        B: BatterySensor,
+        E: ConfigStorage,
+    >(
+        self,
+        robot: &mut Robot<M, R, C, S, L, B, E>,
+    ) {
         match self {
-            Stick::Right(movement) => {
-                robot.right_stick_management(movement);
-                //robot.throttle_management(&movement.up_down);
-                //robot.turn_management(&movement.right_left);
-            }
-            //   Stick::Left(movement) => {
-            //       //robot.potency_management(&movement.up_down);
-            //       robot.rotation_management(&movement.right_left);
-            //   }
+            Stick::Right(movement) => robot.right_stick_management(movement),
+            Stick::Left(movement) => robot.left_stick_management(movement),
+            Stick::Vra(movement) => robot.vra_management(movement),
             _ => {}
         }
     }
 }
 
-/// Loads and configures timer 0 for PWM.
-fn load_timer0_pwm(tc0: TC0) -> Timer0Pwm {
-    Timer0Pwm::new(tc0, arduino_hal::simple_pwm::Prescaler::Prescale64)
+#[allow(unused)]
+pub struct Robot<
+    M: Motors,
+    R: RcInput,
+    C: Clock,
+    S: SerialPort,
+    L: StatusLed,
+    B: BatterySensor,
+    E: ConfigStorage,
+> {
+    serial: S,
+    led: L,
+    battery_sensor: B,
+    motors: M,
+    flysky: FlySkyManager<R>,
+    clock: C,
+    failsafe: Failsafe,
+    arming: Arming,
+    arming_state: ArmingState,
+    battery: BatteryMonitor,
+    battery_state: BatteryState,
+    failsafe_engaged: bool,
+    config: Config,
+    channels: [PositionValue; NUM_STICK_CHANNELS],
+    last_frame_ms: Option<u32>,
+    frames: FrameStats,
+    kinematics: Kinematics,
+    // Speed of the left side from the left stick, in tank kinematics, which only
+    // drives once that stick was seen centered
+    left_track: i16,
+    tank_ready: bool,
+    // Position of the VRA knob and gear of the switch, for the speed limit
+    speed_knob: PositionValue,
+    gear: Option<Gear>,
+    stop_style: StopStyle,
+    stop_timer: StopTimer,
+    drive: DriveCommand,
+    ramp: WheelRamp,
+    wheels: WheelOutputs,
+    stats: LoopStats,
+    last_telemetry_ms: u32,
+    shell: LineBuffer,
+    motor_test: Option<MotorTest>,
+    calibrator: Option<Calibrator>,
+    calibration_gesture: CalibrationGesture,
+    storage: E,
 }
 
-/// Loads and configures timer 2 for PWM.
-fn load_timer2_pwm(tc2: TC2) -> Timer2Pwm {
-    Timer2Pwm::new(tc2, arduino_hal::simple_pwm::Prescaler::Prescale64)
-}
+impl<
+        M: Motors,
+        R: RcInput,
+        C: Clock,
+        S: SerialPort,
+        L: StatusLed,
+        B: BatterySensor,
+        E: ConfigStorage,
+    > Robot<M, R, C, S, L, B, E>
+{
+    /// Creates a new Robot instance from its hardware, with the settings saved in
+    /// its storage or `defaults` if none are valid. The control loop ticks at the
+    /// rate of the settings.
+    pub fn new(hardware: Hardware<M, R, C, S, L, B, E>, defaults: Config) -> Self {
+        let mut storage = hardware.storage;
+        let loaded = store::load(&mut storage);
+        let config = loaded.unwrap_or(defaults);
+        hardware
+            .clock
+            .set_control_rate(config.control_rate_hz as u16);
+        let mut robot = Self {
+            serial: hardware.serial,
+            led: hardware.led,
+            battery_sensor: hardware.battery,
+            motors: hardware.motors,
+            flysky: FlySkyManager::new(hardware.rc_input),
+            clock: hardware.clock,
+            failsafe: Failsafe::new(config.failsafe_timeout_ms as u32),
+            arming: Arming::new(config.arming, config.center_band),
+            arming_state: ArmingState::Disarmed,
+            battery: BatteryMonitor::new(config.battery),
+            battery_state: BatteryState::Ok,
+            failsafe_engaged: false,
+            config,
+            channels: [0; NUM_STICK_CHANNELS],
+            last_frame_ms: None,
+            frames: FrameStats::default(),
+            kinematics: Kinematics::Mecanum,
+            left_track: 0,
+            tank_ready: false,
+            speed_knob: MID_POSITION,
+            gear: None,
+            stop_style: config.stop.style,
+            stop_timer: StopTimer::default(),
+            drive: DriveCommand::default(),
+            ramp: WheelRamp::default(),
+            wheels: WheelOutputs::default(),
+            stats: LoopStats::default(),
+            last_telemetry_ms: 0,
+            shell: LineBuffer::new(),
+            motor_test: None,
+            calibrator: None,
+            calibration_gesture: CalibrationGesture::default(),
+            storage,
+        };
+        match loaded {
+            Ok(_) => ufmt::uwrite!(&mut robot.serial, "settings loaded\r\n"),
+            Err(error) => ufmt::uwrite!(
+                &mut robot.serial,
+                "settings {}, using defaults\r\n",
+                error.message()
+            ),
+        }
+        .unwrap_infallible();
+        robot
+    }
 
-/// Initializes the FlySky manager with the given peripherals.
-fn load_flysky_manager(peripherals: &Peripherals) -> FlySkyManager {
-    FlySkyManager::init(&peripherals, flysky::FlySkyPpmPin::D2)
-}
+    /// Processes all FlySky sticks inputs and updates robot state.
+    fn process_flysky_sticks(&mut self) {
+        if !self.battery_management() {
+            return;
+        }
+        let flysky = self.flysky.get_status(
+            self.clock.millis(),
+            &self.config.channel_map,
+            self.config.center_band,
+        );
+        self.channels = flysky.channels;
+        self.last_frame_ms = flysky.signal.last_frame_ms;
+        self.frames = flysky.signal.frames;
+        if !self.failsafe_management(&flysky) {
+            return;
+        }
+        if !self.calibration_management(&flysky) {
+            return;
+        }
+        self.kinematics_management(&flysky);
+        self.gear_management(&flysky);
+        self.stop_management(&flysky);
+        if !self.arming_management(&flysky) || !self.tank_management(&flysky) {
+            return;
+        }
+        flysky.left.process(self);
+        flysky.right.process(self);
+        flysky.vra.process(self);
+        flysky.vrb.process(self);
+        self.drive_management();
+    }
 
-pub struct MotorA {
-    d5: arduino_hal::port::Pin<mode::PwmOutput<Timer0Pwm>, PD5>,
-    d4: Pin<arduino_hal::port::mode::Output, PD4>,
-    d7: Pin<arduino_hal::port::mode::Output, PD7>,
-}
+    /// Updates the failsafe with the signal status and stops the motors while it is
+    /// engaged. Changes are reported on serial. Returns true if the robot may drive.
+    fn failsafe_management(&mut self, flysky: &FlySky) -> bool {
+        let engaged = self
+            .failsafe
+            .update(&flysky.signal, flysky.sticks_centered())
+            .is_engaged();
+        if engaged != self.failsafe_engaged {
+            self.failsafe_engaged = engaged;
+            let message = if engaged { "failsafe" } else { "signal ok" };
+            ufmt::uwrite!(&mut self.serial, "{}\r\n", message).unwrap_infallible();
+        }
+        if engaged {
+            self.stop_motors();
+        }
+        !engaged
+    }
 
-pub struct MotorB {
-    d6: arduino_hal::port::Pin<mode::PwmOutput<Timer0Pwm>, PD6>,
-    d8: Pin<arduino_hal::port::mode::Output, PB0>,
-    d12: Pin<arduino_hal::port::mode::Output, PB4>,
-}
+    /// Runs the stick calibration, started from the shell or by holding both sticks
+    /// down-left while disarmed. The result is saved at once. The motors stay stopped
+    /// and the LED blinks meanwhile. Returns true if no calibration is running.
+    fn calibration_management(&mut self, flysky: &FlySky) -> bool {
+        let now_ms = flysky.signal.now_ms;
+        if self.calibrator.is_none() {
+            if self.arming_state.is_armed()
+                || !self.calibration_gesture.update(&flysky.channels, now_ms)
+            {
+                return true;
+            }
+            self.start_calibration(now_ms);
+        }
+        let Some(calibrator) = self.calibrator.as_mut() else {
+            return true;
+        };
+        let step = if flysky.signal.new_frame {
+            calibrator.update(&flysky.channels, now_ms)
+        } else {
+            CalibrationStep::Sweeping
+        };
+        self.stop_motors();
+        self.led.set((now_ms / CALIBRATION_BLINK_MS) & 1 == 0);
+        match step {
+            CalibrationStep::Sweeping => {}
+            CalibrationStep::Done(calibration) => {
+                self.calibrator = None;
+                self.config.calibration = calibration;
+                if store::save(&mut self.storage, &self.config) {
+                    ufmt::uwrite!(&mut self.serial, "calibration saved\r\n").unwrap_infallible();
+                } else {
+                    self.report_error("storage write failed");
+                }
+            }
+            CalibrationStep::TimedOut => {
+                self.calibrator = None;
+                self.report_error("calibration timed out");
+            }
+        }
+        false
+    }
 
-pub struct MotorC {
-    d11: arduino_hal::port::Pin<mode::PwmOutput<Timer2Pwm>, PB3>,
-    d10: Pin<arduino_hal::port::mode::Output, PB2>,
-    d9: Pin<arduino_hal::port::mode::Output, PB1>,
-}
+    /// Starts recording the sticks, which the pilot is told to sweep.
+    fn start_calibration(&mut self, now_ms: u32) {
+        self.end_motor_test();
+        self.calibrator = Some(Calibrator::new(now_ms));
+        ufmt::uwrite!(
+            &mut self.serial,
+            "calibrating: move every stick and knob to both ends, then release the sticks\r\n"
+        )
+        .unwrap_infallible();
+    }
 
-pub struct MotorD {
-    d3: arduino_hal::port::Pin<mode::PwmOutput<Timer2Pwm>, PD3>,
-    d13: Pin<arduino_hal::port::mode::Output, PB5>,
-    a0: Pin<arduino_hal::port::mode::Output, PC0>,
-}
+    /// Reads the battery voltage and stops the motors once it is cut off.
+    /// Changes are reported on serial. Returns true if the battery still allows driving.
+    fn battery_management(&mut self) -> bool {
+        let sample_mv = self.battery_sensor.read_millivolts();
+        let state = self.battery.update(sample_mv);
+        if state != self.battery_state {
+            self.battery_state = state;
+            let message = match state {
+                BatteryState::Ok => "battery ok",
+                BatteryState::Low => "battery low",
+                BatteryState::Cutoff => "battery cutoff",
+            };
+            ufmt::uwrite!(
+                &mut self.serial,
+                "{}: {}mV\r\n",
+                message,
+                self.battery.millivolts()
+            )
+            .unwrap_infallible();
+        }
+        if state == BatteryState::Cutoff {
+            self.stop_motors();
+        }
+        state != BatteryState::Cutoff
+    }
 
-trait Motor {
-    fn forward(&mut self, value: u8);
-    fn backward(&mut self, value: u8);
-    fn stop(&mut self);
-}
+    /// Updates the arming state from the sticks and reports it on the LED and serial.
+    /// Returns true if the robot may drive in this step.
+    fn arming_management(&mut self, flysky: &FlySky) -> bool {
+        let previous = self.arming_state;
+        let switch = match self.config.arming {
+            ArmMode::Gesture => None,
+            ArmMode::Switch(function) => flysky.switch(function),
+        };
+        self.arming_state = self
+            .arming
+            .update(&flysky.channels, switch, flysky.signal.now_ms);
+        self.led.set(self.arming_state.is_armed());
+        if !self.arming_state.is_armed() {
+            self.tank_ready = false;
+        }
+        match (previous, self.arming_state) {
+            (ArmingState::Disarmed, ArmingState::Disarmed) => {
+                self.stop_motors();
+                false
+            }
+            (ArmingState::Armed, ArmingState::Disarmed) => {
+                self.stop_motors();
+                ufmt::uwrite!(&mut self.serial, "disarmed\r\n").unwrap_infallible();
+                false
+            }
+            (ArmingState::Disarmed, ArmingState::Armed) => {
+                // Skip the step that completed the gesture
+                ufmt::uwrite!(&mut self.serial, "armed\r\n").unwrap_infallible();
+                false
+            }
+            (ArmingState::Armed, ArmingState::Armed) => true,
+        }
+    }
 
-impl Motor for MotorA {
-    fn forward(&mut self, value: u8) {
-        self.d5.enable();
-        self.d5.set_duty(value);
-        self.d4.set_low();
-        self.d7.set_high();
-    }
-    fn backward(&mut self, value: u8) {
-        self.d5.enable();
-        self.d5.set_duty(value);
-        self.d4.set_high();
-        self.d7.set_low();
-    }
-    fn stop(&mut self) {
-        self.d5.set_duty(0);
-        self.d5.disable();
-        self.d4.set_high();
-        self.d7.set_high();
+    /// Selects the kinematics from the config or SWC, and reports a change.
+    fn kinematics_management(&mut self, flysky: &FlySky) {
+        let kinematics = self.config.drive.select(flysky.swc);
+        if kinematics != self.kinematics {
+            self.kinematics = kinematics;
+            self.tank_ready = false;
+            ufmt::uwrite!(&mut self.serial, "drive: {}\r\n", kinematics.name()).unwrap_infallible();
+        }
     }
-}
 
-impl Motor for MotorB {
-    fn forward(&mut self, value: u8) {
-        self.d6.enable();
-        self.d6.set_duty(value);
-        self.d8.set_low();
-        self.d12.set_high();
-    }
-    fn backward(&mut self, value: u8) {
-        self.d6.enable();
-        self.d6.set_duty(value);
-        self.d8.set_high();
-        self.d12.set_low();
-    }
-    fn stop(&mut self) {
-        self.d6.set_duty(0);
-        self.d6.disable();
-        self.d8.set_high();
-        self.d12.set_high();
+    /// Holds the robot in tank kinematics until the left stick reads centered, so a
+    /// throttle resting down does not spin the robot when tank is selected or the
+    /// robot is armed. Returns true if the robot may drive.
+    fn tank_management(&mut self, flysky: &FlySky) -> bool {
+        if self.kinematics != Kinematics::Tank || self.tank_ready {
+            return true;
+        }
+        self.tank_ready = flysky.left_vertical_centered();
+        if !self.tank_ready {
+            self.stop_motors();
+        }
+        self.tank_ready
     }
-}
 
-impl Motor for MotorC {
-    fn forward(&mut self, value: u8) {
-        self.d11.enable();
-        self.d11.set_duty(value);
-        self.d10.set_low();
-        self.d9.set_high();
-    }
-    fn backward(&mut self, value: u8) {
-        self.d11.enable();
-        self.d11.set_duty(value);
-        self.d10.set_high();
-        self.d9.set_low();
-    }
-    fn stop(&mut self) {
-        self.d11.set_duty(0);
-        self.d11.disable();
-        self.d10.set_low();
-        self.d9.set_low();
+    /// Selects the gear from its switch, if one is configured, and reports a change.
+    fn gear_management(&mut self, flysky: &FlySky) {
+        let gear = self
+            .config
+            .speed
+            .gear_switch
+            .and_then(|function| flysky.switch(function))
+            .map(Gear::from_switch);
+        if gear != self.gear {
+            self.gear = gear;
+            if let Some(gear) = gear {
+                ufmt::uwrite!(&mut self.serial, "gear: {}\r\n", gear.name()).unwrap_infallible();
+            }
+        }
     }
-}
 
-impl Motor for MotorD {
-    fn forward(&mut self, value: u8) {
-        self.d3.enable();
-        self.d3.set_duty(value);
-        self.d13.set_low();
-        self.a0.set_high();
-    }
-    fn backward(&mut self, value: u8) {
-        self.d3.enable();
-        self.d3.set_duty(value);
-        self.d13.set_high();
-        self.a0.set_low();
-    }
-    fn stop(&mut self) {
-        self.d3.set_duty(0);
-        self.d3.disable();
-        self.d13.set_low();
-        self.a0.set_low();
+    /// Selects how the wheels stop, with the switch if one is set. Changes made by
+    /// the switch are reported on serial.
+    fn stop_management(&mut self, flysky: &FlySky) {
+        let selected = self
+            .config
+            .stop
+            .switch
+            .and_then(|function| flysky.switch(function))
+            .map(StopStyle::from_switch);
+        let style = selected.unwrap_or(self.config.stop.style);
+        if style != self.stop_style {
+            self.stop_style = style;
+            if selected.is_some() {
+                ufmt::uwrite!(&mut self.serial, "stop: {}\r\n", style.name()).unwrap_infallible();
+            }
+        }
     }
-}
 
-#[derive(Default)]
-pub struct PwmValues {
-    pwm_y: u8,
-    pwm_x: u8,
-}
+    /// Reads the VRA knob, which limits the speed when enabled.
+    fn vra_management(&mut self, movement: StickMovement) {
+        self.speed_knob = movement.right_left.value();
+    }
 
-#[allow(unused)]
-pub struct Robot {
-    serial: Usart<
-        arduino_hal::pac::USART0,
-        Pin<arduino_hal::port::mode::Input, PD0>,
-        Pin<arduino_hal::port::mode::Output, PD1>,
-    >,
-    motor_a: MotorA,
-    motor_b: MotorB,
-    motor_c: MotorC,
-    motor_d: MotorD,
-    flysky: FlySkyManager,
-    tick_duration_us: u32,
-    pwm_values: PwmValues,
-}
+    /// Sets the forward and lateral speed from the right stick. In tank kinematics
+    /// its vertical axis drives the right side instead.
+    fn right_stick_management(&mut self, movement: StickMovement) {
+        // adelante / atrás
+        let forward = self.config.axes[CHANNEL_1].apply(
+            movement.up_down.value(),
+            &self.config.calibration[CHANNEL_1],
+        );
+        // derecha / izquierda lateral
+        let lateral = self.config.axes[CHANNEL_0].apply(
+            movement.right_left.value(),
+            &self.config.calibration[CHANNEL_0],
+        );
+        match self.kinematics {
+            Kinematics::Mecanum => {
+                self.drive.vy = forward;
+                self.drive.vx = lateral;
+            }
+            Kinematics::Arcade => {
+                self.drive.vy = forward;
+                self.drive.vx = 0;
+            }
+            Kinematics::Tank => self.drive = kinematics::tank(self.left_track, forward),
+        }
+    }
 
-impl Robot {
-    /// Creates a new Robot instance and initializes peripherals.
-    pub fn new(peripherals: Peripherals, baudrate: u32, tick_duration_us: u32) -> Self {
-        // Init PPM protocol of flysky radio control
-        let flysky = load_flysky_manager(&peripherals);
-        let timer0 = load_timer0_pwm(peripherals.TC0);
-        let timer2 = load_timer2_pwm(peripherals.TC2);
-        let pins = pins!(peripherals);
-        let serial = default_serial!(peripherals, pins, baudrate);
-
-        let motor_a = MotorA {
-            d5: pins.d5.into_output().into_pwm(&timer0),
-            d4: pins.d4.into_output(),
-            d7: pins.d7.into_output(),
-        };
+    /// Sets the rotation speed from the horizontal axis of the left stick. In tank
+    /// kinematics its vertical axis drives the left side instead.
+    fn left_stick_management(&mut self, movement: StickMovement) {
+        if self.kinematics == Kinematics::Tank {
+            self.left_track = self.config.axes[CHANNEL_2].apply(
+                movement.up_down.value(),
+                &self.config.calibration[CHANNEL_2],
+            );
+            return;
+        }
+        // giro horario / antihorario
+        self.drive.omega = self.config.axes[CHANNEL_3].apply(
+            movement.right_left.value(),
+            &self.config.calibration[CHANNEL_3],
+        );
+    }
 
-        let motor_b = MotorB {
-            d6: pins.d6.into_output().into_pwm(&timer0),
-            d8: pins.d8.into_output(),
-            d12: pins.d12.into_output(),
-        };
+    /// Mixes the requested translation and rotation with the selected kinematics and
+    /// drives the four wheels, limited to the speed of the knob and gear and to the
+    /// power the battery allows, and ramping each of them towards its new duty.
+    fn drive_management(&mut self) {
+        let limit = self.config.speed.limit_percent(
+            self.gear,
+            self.speed_knob,
+            &self.config.calibration[CHANNEL_4],
+        );
+        let target = self
+            .kinematics
+            .mix(self.drive, limit)
+            .scaled(self.battery.power_percent());
+        self.wheels = self.ramp.apply(target, self.config.ramp);
 
-        let motor_c = MotorC {
-            d11: pins.d11.into_output().into_pwm(&timer2),
-            d10: pins.d10.into_output(),
-            d9: pins.d9.into_output(),
-        };
+        // Apply direction and magnitud of each motor
+        for wheel in Wheel::ALL {
+            self.drive_wheel(wheel, wheel.duty(self.wheels));
+        }
+    }
 
-        let motor_d = MotorD {
-            d3: pins.d3.into_output().into_pwm(&timer2),
-            d13: pins.d13.into_output(),
-            a0: pins.a0.into_output(),
-        };
+    /// Drives a wheel at a signed duty, through the motor and direction of the
+    /// wheel map, scaled into the range its motor turns in.
+    fn drive_wheel(&mut self, wheel: Wheel, duty: i16) {
+        let (id, duty) = self.config.wheels.route(wheel, duty);
+        let duty = deadband::compensate(duty, self.config.motor_min_duty[id as usize]);
+        self.drive_motor(id, duty);
+    }
 
-        Self {
-            serial,
-            motor_a,
-            motor_b,
-            motor_c,
-            motor_d,
-            flysky,
-            tick_duration_us,
-            pwm_values: PwmValues::default(),
+    /// Drives a motor at a signed duty. A zero duty stops it in the selected style.
+    fn drive_motor(&mut self, id: MotorId, duty: i16) {
+        if duty == 0 {
+            self.stop_motor(id);
+        } else {
+            self.stop_timer.driven(id);
+            apply_motor(self.motors.get(id), duty);
         }
     }
 
-    /// Processes all FlySky sticks inputs and updates robot state.
-    fn process_flysky_sticks(&mut self) {
-        let flysky = self.flysky.get_status();
-        flysky.left.process(self);
-        flysky.right.process(self);
-        flysky.vra.process(self);
-        flysky.vrb.process(self);
-        ufmt::uwrite!(&mut self.serial, "\r\n").unwrap_infallible();
+    /// Stops a motor in the selected style, braking first if it was just driven.
+    fn stop_motor(&mut self, id: MotorId) {
+        let mode = self.stop_timer.stop_mode(
+            id,
+            self.stop_style,
+            self.config.stop.brake_ms,
+            self.clock.millis(),
+        );
+        let motor = self.motors.get(id);
+        motor.set_stop_mode(mode);
+        motor.stop();
     }
 
-    fn right_stick_management(&mut self, movement: StickMovement) {
-        let y = match movement.up_down {
-            Position::Up(v) => helper::map_u16_to_i16(v, self.pwm_values.pwm_y), // adelante
-            Position::Down(v) => helper::map_u16_to_i16(v, self.pwm_values.pwm_y), // atrás
-            _ => 0,
+    /// Stops all four motors at once, bypassing the ramp.
+    fn stop_motors(&mut self) {
+        for id in MotorId::ALL {
+            self.stop_motor(id);
+        }
+        self.ramp.reset();
+        self.drive = DriveCommand::default();
+        self.wheels = WheelOutputs::default();
+    }
+
+    /// Runs one step of the control loop, `elapsed_ticks` ticks after the last one,
+    /// and records how long it took.
+    fn control_step(&mut self, elapsed_ticks: u16) {
+        let start_us = self.clock.micros();
+        self.shell_management();
+        if self.motor_test.is_some() {
+            // The sticks are not read during a test, the battery still must be
+            if self.battery_management() {
+                self.motor_test_management();
+            } else {
+                self.end_motor_test();
+                self.report_error("battery cutoff");
+            }
+        } else {
+            self.process_flysky_sticks();
+        }
+        let step_us = self.clock.micros().wrapping_sub(start_us);
+        self.stats.record(elapsed_ticks, step_us);
+        self.telemetry_management();
+    }
+
+    /// Reads the bytes received on serial and runs the complete command lines.
+    fn shell_management(&mut self) {
+        for _ in 0..MAX_SHELL_BYTES_PER_STEP {
+            let Some(byte) = self.serial.read_byte() else {
+                break;
+            };
+            if let Some(MotorTest {
+                target: TestTarget::MinDuty(id),
+                duty,
+                ..
+            }) = self.motor_test
+            {
+                // Any key but the line endings, which may still follow the command,
+                // confirms the motor turns
+                if byte == b'\r' || byte == b'\n' {
+                    continue;
+                }
+                self.end_motor_test();
+                self.shell = LineBuffer::new();
+                self.config.motor_min_duty[id as usize] = duty as u8;
+                ufmt::uwrite!(
+                    &mut self.serial,
+                    "motor.{}.min_duty = {}, save to keep it\r\n",
+                    shell::motor_name(id),
+                    duty
+                )
+                .unwrap_infallible();
+                continue;
+            }
+            match self.shell.push(byte) {
+                Some(Ok(line)) => match shell::parse(line.as_str()) {
+                    Ok(command) => self.run_command(command),
+                    Err(error) => self.report_error(error.message()),
+                },
+                Some(Err(error)) => self.report_error(error.message()),
+                None => {}
+            }
+        }
+    }
+
+    /// Runs a command of the serial shell and writes its answer.
+    fn run_command(&mut self, command: Command) {
+        match command {
+            Command::Help => ufmt::uwrite!(&mut self.serial, "{}", shell::HELP).unwrap_infallible(),
+            Command::Status => self.report_status(),
+            Command::Get(Some(setting)) => self.report_setting(setting),
+            Command::Get(None) => {
+                for setting in Setting::all() {
+                    self.report_setting(setting);
+                }
+            }
+            Command::Set(setting, value) => match setting.set(&mut self.config, value) {
+                Ok(()) => {
+                    self.battery.set_config(self.config.battery);
+                    self.arming.set_mode(self.config.arming);
+                    self.arming.set_center_band(self.config.center_band);
+                    self.failsafe
+                        .set_timeout_ms(self.config.failsafe_timeout_ms as u32);
+                    self.clock
+                        .set_control_rate(self.config.control_rate_hz as u16);
+                    self.report_setting(setting);
+                }
+                Err(error) => self.report_error(error.message()),
+            },
+            Command::Save => {
+                if store::save(&mut self.storage, &self.config) {
+                    ufmt::uwrite!(&mut self.serial, "ok\r\n").unwrap_infallible();
+                } else {
+                    self.report_error("storage write failed");
+                }
+            }
+            Command::MotorTest(id, duty) => self.start_motor_test(TestTarget::Motor(id), duty),
+            Command::WheelTest(duty) => self.start_motor_test(TestTarget::Wheels(0), duty),
+            Command::MotorStart(id) => {
+                self.start_motor_test(TestTarget::MinDuty(id), MIN_DUTY_STEP)
+            }
+            Command::Calibrate => {
+                if self.arming_state.is_armed() {
+                    self.report_error("disarm first");
+                } else {
+                    self.start_calibration(self.clock.millis());
+                }
+            }
+            Command::Arm => {
+                if self.failsafe_engaged || !self.signal_fresh() {
+                    self.report_error("no signal");
+                } else if self.calibrator.is_some() {
+                    self.report_error("calibrating");
+                } else if !arming::drive_centered(&self.channels, self.config.center_band) {
+                    self.report_error("center the right stick");
+                } else {
+                    self.end_motor_test();
+                    self.arming.force(ArmingState::Armed);
+                }
+            }
+            Command::Disarm => self.arming.force(ArmingState::Disarmed),
+        }
+    }
+
+    /// Returns true if the last frame arrived within the failsafe timeout.
+    fn signal_fresh(&self) -> bool {
+        let now_ms = self.clock.millis();
+        matches!(self.last_frame_ms,
+            Some(last_frame_ms) if now_ms.wrapping_sub(last_frame_ms) <= self.failsafe.timeout_ms())
+    }
+
+    /// Starts running `target` at `duty`, if the robot is disarmed and idle.
+    fn start_motor_test(&mut self, target: TestTarget, duty: i16) {
+        if self.arming_state.is_armed() {
+            self.report_error("disarm first");
+        } else if self.calibrator.is_some() {
+            self.report_error("calibrating");
+        } else if self.battery_state == BatteryState::Cutoff {
+            self.report_error("battery cutoff");
+        } else {
+            self.stop_motors();
+            ufmt::uwrite!(&mut self.serial, "ok\r\n").unwrap_infallible();
+            self.run_motor_test(target, duty);
+        }
+    }
+
+    /// Runs `target` at `duty` for the test time, telling the pilot what to watch.
+    fn run_motor_test(&mut self, target: TestTarget, duty: i16) {
+        let test_ms = match target {
+            TestTarget::Motor(_) => MOTOR_TEST_MS,
+            TestTarget::Wheels(index) => {
+                let wheel = Wheel::ALL[index];
+                let (id, _) = self.config.wheels.route(wheel, duty);
+                ufmt::uwrite!(
+                    &mut self.serial,
+                    "wheel: {} on motor {}\r\n",
+                    wheel.description(),
+                    shell::motor_name(id)
+                )
+                .unwrap_infallible();
+                MOTOR_TEST_MS
+            }
+            TestTarget::MinDuty(id) => {
+                ufmt::uwrite!(
+                    &mut self.serial,
+                    "motor {}: press space once the wheel turns\r\n",
+                    shell::motor_name(id)
+                )
+                .unwrap_infallible();
+                MIN_DUTY_STEP_MS
+            }
         };
+        let until_ms = self.clock.millis().wrapping_add(test_ms);
+        self.motor_test = Some(MotorTest {
+            target,
+            duty,
+            until_ms,
+        });
+    }
 
-        let x = match movement.right_left {
-            Position::Right(v) => helper::map_u16_to_i16(v, self.pwm_values.pwm_x), // derecha lateral
-            Position::Left(v) => helper::map_u16_to_i16(v, self.pwm_values.pwm_x), // izquierda lateral
-            _ => 0,
+    /// Runs the motor or wheel under test until its time is up, then the next wheel
+    /// of a wheel test or the next duty of a min duty search.
+    fn motor_test_management(&mut self) {
+        let Some(test) = self.motor_test else {
+            return;
         };
+        let now_ms = self.clock.millis();
+        // Signed difference, the deadline may be across the wraparound
+        if (now_ms.wrapping_sub(test.until_ms) as i32) < 0 {
+            match test.target {
+                TestTarget::Motor(id) | TestTarget::MinDuty(id) => self.drive_motor(id, test.duty),
+                TestTarget::Wheels(index) => self.drive_wheel(Wheel::ALL[index], test.duty),
+            }
+            return;
+        }
+        match test.target {
+            TestTarget::MinDuty(id) if test.duty < MAX_MIN_DUTY as i16 => {
+                // Keep the motor running, only the duty steps up
+                let duty = (test.duty + MIN_DUTY_STEP).min(MAX_MIN_DUTY as i16);
+                self.motor_test = Some(MotorTest {
+                    duty,
+                    until_ms: now_ms.wrapping_add(MIN_DUTY_STEP_MS),
+                    ..test
+                });
+                self.drive_motor(id, duty);
+            }
+            TestTarget::MinDuty(_) => {
+                self.end_motor_test();
+                self.report_error("motor did not start");
+            }
+            TestTarget::Wheels(index) if index + 1 < Wheel::ALL.len() => {
+                self.end_motor_test();
+                self.run_motor_test(TestTarget::Wheels(index + 1), test.duty);
+            }
+            TestTarget::Wheels(_) => {
+                self.end_motor_test();
+                ufmt::uwrite!(&mut self.serial, "wheel test done\r\n").unwrap_infallible();
+            }
+            TestTarget::Motor(_) => self.end_motor_test(),
+        }
+    }
+
+    /// Stops the motor test, if one is running.
+    fn end_motor_test(&mut self) {
+        if self.motor_test.take().is_some() {
+            self.stop_motors();
+        }
+    }
 
-        self.pwm_values.pwm_y = y as u8;
-        self.pwm_values.pwm_x = x as u8;
+    /// Writes the state, battery and loop timing.
+    fn report_status(&mut self) {
+        let state = if self.arming_state.is_armed() {
+            "armed"
+        } else if self.calibrator.is_some() {
+            "calibrating"
+        } else {
+            "disarmed"
+        };
+        let signal = if self.failsafe_engaged { "lost" } else { "ok" };
+        ufmt::uwrite!(
+            &mut self.serial,
+            "state: {}, signal: {}, battery: {}mV, power: {}%\r\n",
+            state,
+            signal,
+            self.battery.millivolts(),
+            self.battery.power_percent()
+        )
+        .unwrap_infallible();
+        ufmt::uwrite!(
+            &mut self.serial,
+            "loop: {}us, max: {}us, overruns: {}\r\n",
+            self.stats.last_step_us,
+            self.stats.max_step_us,
+            self.stats.overruns
+        )
+        .unwrap_infallible();
+        ufmt::uwrite!(
+            &mut self.serial,
+            "frames: {} good, {} bad, {} missed\r\n",
+            self.frames.good,
+            self.frames.bad,
+            self.frames.missed
+        )
+        .unwrap_infallible();
+    }
 
-        let r = 0; // Todo: Implement left stick
+    /// Writes a setting as `key = value`.
+    fn report_setting(&mut self, setting: Setting) {
+        setting.write_key(&mut self.serial).unwrap_infallible();
+        ufmt::uwrite!(&mut self.serial, " = {}\r\n", setting.get(&self.config)).unwrap_infallible();
+    }
 
-        let mut a = y + x + r;
-        let mut b = y - x - r;
-        let mut c = y - x + r;
-        let mut d = y + x - r;
+    /// Writes an error answer of the shell.
+    fn report_error(&mut self, message: &str) {
+        ufmt::uwrite!(&mut self.serial, "error: {}\r\n", message).unwrap_infallible();
+    }
 
-        // Normalize values from 0–255
-        let max = a.abs().max(b.abs().max(c.abs().max(d.abs()))) as f32;
-        if max > 255.0 {
-            let scale = 255.0 / max;
-            a = (a as f32 * scale) as i16;
-            b = (b as f32 * scale) as i16;
-            c = (c as f32 * scale) as i16;
-            d = (d as f32 * scale) as i16;
+    /// Sends a binary telemetry frame at the configured rate.
+    fn telemetry_management(&mut self) {
+        let rate_hz = self.config.telemetry_rate_hz as u32;
+        let now_ms = self.clock.millis();
+        if rate_hz == 0 || now_ms.wrapping_sub(self.last_telemetry_ms) < 1000 / rate_hz {
+            return;
         }
+        self.last_telemetry_ms = now_ms;
+        let frame = self.telemetry(now_ms).encode();
+        self.serial.write_bytes(&frame);
+        self.stats.reset_max();
+    }
 
-        ufmt::uwrite!(self.serial, "a: {}, b: {}, c: {}, d: {}", a, b, c, d).unwrap_infallible();
-        // Apply direction and magnitud of each motor
-        apply_motor(&mut self.motor_a, a);
-        apply_motor(&mut self.motor_b, b);
-        apply_motor(&mut self.motor_c, c);
-        apply_motor(&mut self.motor_d, d);
+    /// Returns a snapshot of the robot at `now_ms`.
+    fn telemetry(&self, now_ms: u32) -> Telemetry {
+        let WheelOutputs { a, b, c, d } = self.wheels;
+        let mut flags = 0;
+        if self.arming_state.is_armed() {
+            flags |= telemetry::FLAG_ARMED;
+        }
+        if self.failsafe_engaged {
+            flags |= telemetry::FLAG_FAILSAFE;
+        }
+        match self.battery_state {
+            BatteryState::Ok => {}
+            BatteryState::Low => flags |= telemetry::FLAG_BATTERY_LOW,
+            BatteryState::Cutoff => flags |= telemetry::FLAG_BATTERY_CUTOFF,
+        }
+        Telemetry {
+            time_ms: now_ms,
+            channels: self.channels,
+            frames: self.frames,
+            vx: self.drive.vx,
+            vy: self.drive.vy,
+            omega: self.drive.omega,
+            wheels: [a, b, c, d],
+            battery_mv: self.battery.millivolts(),
+            last_step_us: saturate_u16(self.stats.last_step_us),
+            max_step_us: saturate_u16(self.stats.max_step_us),
+            overruns: saturate_u16(self.stats.overruns),
+            flags,
+        }
     }
 
-    /// Starts the robot's main loop, processing inputs and updating state.
+    /// Starts the robot's main loop, processing inputs and updating state once per
+    /// tick of the clock.
     pub fn start(&mut self) -> ! {
+        // Discard the ticks elapsed while booting
+        self.clock.wait_for_tick();
         loop {
-            self.process_flysky_sticks();
-            arduino_hal::delay_us(self.tick_duration_us);
+            let elapsed_ticks = self.clock.wait_for_tick();
+            self.control_step(elapsed_ticks);
         }
     }
 }
-fn apply_motor(motor: &mut impl Motor, value: i16) {
+
+/// Returns `value` clamped to the range of a u16.
+fn saturate_u16(value: u32) -> u16 {
+    value.min(u16::MAX as u32) as u16
+}
+
+fn apply_motor(motor: &mut dyn Motor, value: i16) {
     if value > 0 {
         motor.forward(value as u8);
     } else if value < 0 {
@@ -306,3 +901,638 @@ fn apply_motor(motor: &mut impl Motor, value: i16) {
         motor.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hal::StopMode;
+    use crate::mock::{
+        MockBattery, MockClock, MockLed, MockMotors, MockRcInput, MockSerial, MockStorage,
+        MotorProbe, MotorState,
+    };
+    use crate::telemetry::{SYNC, TELEMETRY_FRAME_LEN};
+    use std::vec::Vec;
+
+    const FAILSAFE_TIMEOUT_MS: u32 = 250;
+    const CENTERED: [PositionValue; NUM_STICK_CHANNELS] = [1500, 1500, 1000, 1500, 1500, 1500];
+    const ARM_GESTURE: [PositionValue; NUM_STICK_CHANNELS] = [1500, 1500, 1000, 2000, 1500, 1500];
+    const DISARM_GESTURE: [PositionValue; NUM_STICK_CHANNELS] =
+        [1500, 1500, 1000, 1000, 1500, 1500];
+
+    struct Bench {
+        robot: Robot<
+            MockMotors,
+            MockRcInput,
+            MockClock,
+            MockSerial,
+            MockLed,
+            MockBattery,
+            MockStorage,
+        >,
+        motors: MotorProbe,
+        input: MockRcInput,
+        clock: MockClock,
+        serial: MockSerial,
+        led: MockLed,
+        battery: MockBattery,
+        storage: MockStorage,
+    }
+
+    impl Bench {
+        fn new() -> Self {
+            Self::with_storage(MockStorage::default())
+        }
+
+        /// Builds the robot on `storage`, holding the settings of a previous run.
+        fn with_storage(storage: MockStorage) -> Self {
+            let motors = MockMotors::new();
+            let probe = motors.probe();
+            let input = MockRcInput::new();
+            let clock = MockClock::default();
+            let serial = MockSerial::default();
+            let led = MockLed::default();
+            let battery = MockBattery::new(7200);
+            let hardware = Hardware {
+                motors,
+                rc_input: input.clone(),
+                clock: clock.clone(),
+                serial: serial.clone(),
+                led: led.clone(),
+                battery: battery.clone(),
+                storage: storage.clone(),
+            };
+            let robot = Robot::new(hardware, Config::default());
+            serial.take_output();
+            Self {
+                robot,
+                motors: probe,
+                input,
+                clock,
+                serial,
+                led,
+                battery,
+                storage,
+            }
+        }
+
+        /// Advances the clock, delivers a frame and runs one processing step.
+        fn step_with_frame(&mut self, channels: [PositionValue; NUM_STICK_CHANNELS]) {
+            self.step_with_channels(&channels);
+        }
+
+        /// Keeps delivering the same frame until the wheel ramps have settled.
+        fn settle_with_frame(&mut self, channels: [PositionValue; NUM_STICK_CHANNELS]) {
+            self.settle_with_channels(&channels);
+        }
+
+        /// Same as `settle_with_frame`, for frames with any number of channels.
+        fn settle_with_channels(&mut self, channels: &[PositionValue]) {
+            for _ in 0..30 {
+                self.step_with_channels(channels);
+            }
+        }
+
+        /// Same as `step_with_frame`, for frames with any number of channels.
+        fn step_with_channels(&mut self, channels: &[PositionValue]) {
+            self.clock.advance(20);
+            self.input.receive_frame(channels, self.clock.millis());
+            self.robot.process_flysky_sticks();
+        }
+
+        /// Runs control steps for `ms` milliseconds without new frames.
+        fn run_for(&mut self, ms: u32) {
+            for _ in 0..ms / 20 {
+                let elapsed_ticks = self.clock.wait_for_tick();
+                self.robot.control_step(elapsed_ticks);
+            }
+        }
+
+        /// Returns and clears the telemetry frames written so far.
+        fn take_telemetry(&self) -> Vec<Telemetry> {
+            let bytes = self.serial.take_bytes();
+            (0..bytes.len().saturating_sub(TELEMETRY_FRAME_LEN - 1))
+                .filter(|start| bytes[*start..*start + 2] == SYNC)
+                .filter_map(|start| Telemetry::decode(&bytes[start..]).ok())
+                .collect()
+        }
+
+        /// Holds the arming gesture until the robot arms, then centers the sticks.
+        /// The failsafe only clears on centered sticks, so they are centered first.
+        fn arm(&mut self) {
+            self.step_with_frame(CENTERED);
+            for _ in 0..52 {
+                self.step_with_frame(ARM_GESTURE);
+            }
+            self.step_with_frame(CENTERED);
+            self.serial.take_output();
+        }
+    }
+
+    #[test]
+    fn does_not_drive_before_first_frame() {
+        let mut bench = Bench::new();
+        bench.robot.process_flysky_sticks();
+        assert_eq!(bench.motors.state(MotorId::A), MotorState::Stopped);
+        assert!(bench.serial.take_output().contains("failsafe"));
+    }
+
+    #[test]
+    fn drives_forward_with_right_stick_up() {
+        let mut bench = Bench::new();
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.step_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [10, 10, 10, 10]);
+        bench.settle_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn reads_the_sticks_through_the_channel_map() {
+        let mut bench = Bench::new();
+        bench.arm();
+        // Forward from CH5 instead of CH2, reversed
+        bench
+            .serial
+            .send("set map.forward 4\r\nset map.forward.reverse 1\r\n");
+        bench.run_for(60);
+        let mut channels = CENTERED;
+        channels[4] = 1000;
+        bench.settle_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn spins_in_place_with_left_stick() {
+        let mut bench = Bench::new();
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[3] = 2000;
+        bench.settle_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [255, -255, 255, -255]);
+    }
+
+    #[test]
+    fn stops_motors_when_signal_is_lost() {
+        let mut bench = Bench::new();
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.step_with_frame(channels);
+
+        bench.clock.advance(FAILSAFE_TIMEOUT_MS + 1);
+        bench.serial.take_output();
+        bench.robot.process_flysky_sticks();
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(bench.serial.take_output().contains("failsafe"));
+    }
+
+    #[test]
+    fn applies_the_timing_and_center_band_settings() {
+        let mut bench = Bench::new();
+        bench.serial.send("set failsafe.timeout_ms 500\r\n");
+        bench.serial.send("set control.rate 100\r\n");
+        bench.serial.send("set stick.center_band 100\r\n");
+        bench.run_for(60);
+        assert_eq!(bench.clock.tick_us(), 10_000);
+
+        // 80 µs off the middle reads centered, so the shell arms
+        let mut channels = CENTERED;
+        channels[1] = 1580;
+        bench.step_with_frame(channels);
+        bench.robot.run_command(Command::Arm);
+        bench.step_with_frame(channels);
+        assert!(bench.robot.arming_state.is_armed());
+
+        bench.clock.advance(FAILSAFE_TIMEOUT_MS + 1);
+        bench.robot.process_flysky_sticks();
+        assert!(!bench.robot.failsafe_engaged);
+        bench.clock.advance(250);
+        bench.robot.process_flysky_sticks();
+        assert!(bench.robot.failsafe_engaged);
+    }
+
+    #[test]
+    fn does_not_drive_until_armed() {
+        let mut bench = Bench::new();
+        bench.step_with_frame(CENTERED);
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.settle_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(!bench.led.is_on());
+
+        bench.arm();
+        assert!(bench.led.is_on());
+        bench.step_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [10, 10, 10, 10]);
+    }
+
+    #[test]
+    fn disarms_with_reverse_gesture() {
+        let mut bench = Bench::new();
+        bench.arm();
+        for _ in 0..52 {
+            bench.step_with_frame(DISARM_GESTURE);
+        }
+        assert!(!bench.led.is_on());
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(bench.serial.take_output().ends_with("disarmed\r\n"));
+    }
+
+    #[test]
+    fn limits_power_on_low_battery() {
+        let mut bench = Bench::new();
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.battery.set_millivolts(6300);
+        for _ in 0..4 {
+            bench.settle_with_frame(channels);
+        }
+        let duties = bench.motors.signed_duties();
+        assert!((170..=185).contains(&duties[0]), "{duties:?}");
+    }
+
+    #[test]
+    fn stops_motors_on_battery_cutoff() {
+        let mut bench = Bench::new();
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.step_with_frame(channels);
+        bench.battery.set_millivolts(5000);
+        bench.settle_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(bench.serial.take_output().contains("battery cutoff"));
+    }
+
+    #[test]
+    fn drives_without_a_battery_divider() {
+        let mut bench = Bench::new();
+        bench.battery.set_millivolts(0);
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.settle_with_frame(channels);
+        assert_ne!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(!bench.serial.take_output().contains("battery"));
+    }
+
+    #[test]
+    fn tunes_settings_from_the_shell() {
+        let mut bench = Bench::new();
+        bench
+            .serial
+            .send("set ch1.rate 50\r\nset ramp.accel 0\r\nget wheel.fr.invert\r\n");
+        bench.run_for(100);
+        let output = bench.serial.take_output();
+        assert!(output.contains("ch1.rate = 50\r\n"), "{output}");
+        assert!(output.contains("error: value out of range\r\n"));
+        assert!(output.contains("wheel.fr.invert = 0\r\n"));
+        assert_eq!(bench.robot.config.axes[1].rate, 50);
+    }
+
+    #[test]
+    fn keeps_saved_settings_across_restarts() {
+        let mut bench = Bench::new();
+        bench.serial.send("set ch3.expo 30\r\nsave\r\n");
+        bench.run_for(100);
+        assert!(bench
+            .serial
+            .take_output()
+            .contains("ch3.expo = 30\r\nok\r\n"));
+
+        let restarted = Bench::with_storage(bench.storage.clone());
+        assert_eq!(restarted.robot.config.axes[3].expo, 30);
+
+        // A corrupted record falls back to the defaults
+        let mut record = store::encode(&bench.robot.config);
+        record[8] ^= 0xFF;
+        bench.storage.fill(&record);
+        let restarted = Bench::with_storage(bench.storage.clone());
+        assert_eq!(restarted.robot.config, Config::default());
+    }
+
+    #[test]
+    fn calibrates_the_sticks_after_the_gesture() {
+        let mut bench = Bench::new();
+        bench.step_with_frame(CENTERED);
+        for _ in 0..=3000 / 20 {
+            bench.step_with_frame([1000, 1000, 1000, 1000, 1500, 1500]);
+        }
+        assert!(bench.serial.take_output().contains("calibrating"));
+
+        for channels in [[988; NUM_STICK_CHANNELS], [2012; NUM_STICK_CHANNELS]] {
+            for _ in 0..10 {
+                bench.step_with_frame(channels);
+            }
+        }
+        for _ in 0..60 {
+            bench.step_with_frame([1512, 1488, 990, 1506, 1500, 1000]);
+        }
+        assert!(bench.serial.take_output().contains("calibration saved"));
+        assert_eq!(bench.robot.config.calibration[CHANNEL_0].center, 1512);
+        assert_eq!(bench.robot.config.calibration[CHANNEL_1].max, 2012);
+
+        let restarted = Bench::with_storage(bench.storage.clone());
+        assert_eq!(
+            restarted.robot.config.calibration,
+            bench.robot.config.calibration
+        );
+    }
+
+    #[test]
+    fn runs_a_motor_test_only_while_disarmed() {
+        let mut bench = Bench::new();
+        bench.serial.send("motors test b -120\n");
+        bench.run_for(20);
+        assert_eq!(bench.motors.signed_duties(), [0, -120, 0, 0]);
+        bench.run_for(1000);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+
+        bench.arm();
+        bench.serial.send("motors test b 120\n");
+        bench.run_for(20);
+        assert!(bench.serial.take_output().contains("error: disarm first"));
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn runs_each_wheel_in_turn_through_the_wheel_map() {
+        let mut bench = Bench::new();
+        bench
+            .serial
+            .send("set wheel.fr.motor 3\r\nset wheel.rr.invert 1\r\n");
+        bench.run_for(60);
+        bench.serial.send("wheels test 90\r\n");
+        bench.run_for(20);
+        assert_eq!(bench.motors.signed_duties(), [90, 0, 0, 0]);
+        bench.run_for(1020);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 90]);
+        bench.run_for(2000);
+        // Rear right now on motor B, backwards
+        assert_eq!(bench.motors.signed_duties(), [0, -90, 0, 0]);
+        bench.run_for(1000);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        let output = bench.serial.take_output();
+        assert!(
+            output.contains("wheel: front right on motor d\r\n"),
+            "{output}"
+        );
+        assert!(output.contains("wheel: rear right on motor b\r\n"));
+        assert!(output.contains("wheel test done\r\n"));
+    }
+
+    #[test]
+    fn ends_a_motor_test_on_battery_cutoff() {
+        let mut bench = Bench::new();
+        bench.serial.send("motors start a\r\n");
+        bench.run_for(600);
+        assert_ne!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        bench.battery.set_millivolts(5000);
+        bench.run_for(400);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(bench
+            .serial
+            .take_output()
+            .contains("error: battery cutoff\r\n"));
+        // The search is over, it does not start again
+        bench.run_for(400);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn finds_the_min_duty_of_a_motor_and_scales_small_duties_up() {
+        let mut bench = Bench::new();
+        bench.serial.send("motors start c\r\n");
+        bench.run_for(20);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 5, 0]);
+        // 5 more every 200ms, without stopping in between
+        bench.run_for(2600);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 70, 0]);
+        bench.serial.send(" \r\n");
+        bench.run_for(20);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        assert!(bench
+            .serial
+            .take_output()
+            .contains("motor.c.min_duty = 70, save to keep it\r\n"));
+        assert_eq!(bench.robot.config.motor_min_duty, [0, 0, 70, 0]);
+
+        // The first ramp step of the rear left wheel is scaled up from its min duty
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.step_with_frame(channels);
+        assert_eq!(bench.motors.signed_duties(), [10, 10, 77, 10]);
+    }
+
+    #[test]
+    fn arms_and_disarms_from_the_shell() {
+        let mut bench = Bench::new();
+        bench.step_with_frame(CENTERED);
+        bench.serial.send("arm\n");
+        bench.robot.control_step(1);
+        bench.step_with_frame(CENTERED);
+        assert!(bench.led.is_on());
+
+        bench.serial.send("disarm\n");
+        bench.robot.control_step(1);
+        assert!(!bench.led.is_on());
+        assert!(bench.serial.take_output().contains("disarmed"));
+    }
+
+    #[test]
+    fn refuses_to_arm_from_the_shell_unless_ready() {
+        let mut bench = Bench::new();
+        bench.step_with_frame(CENTERED);
+        let mut channels = CENTERED;
+        channels[1] = 1800;
+        bench.step_with_frame(channels);
+        bench.robot.run_command(Command::Arm);
+        assert!(bench
+            .serial
+            .take_output()
+            .contains("error: center the right stick\r\n"));
+
+        // Centered, but the frame went stale since
+        bench.step_with_frame(CENTERED);
+        bench.clock.advance(FAILSAFE_TIMEOUT_MS + 1);
+        bench.robot.run_command(Command::Arm);
+        assert!(bench.serial.take_output().contains("error: no signal\r\n"));
+        bench.step_with_frame(CENTERED);
+        assert!(!bench.robot.arming_state.is_armed());
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn selects_the_kinematics_with_swc() {
+        let mut bench = Bench::new();
+        bench.serial.send("set drive.mode 3\r\n");
+        bench.run_for(20);
+        bench.arm();
+        bench.serial.take_output();
+        // SWC down with the left stick centered, then up: tank, only the left side
+        // drives
+        let mut channels = [1500, 1500, 1500, 1500, 1500, 1500, 1000, 1000, 2000, 1000];
+        bench.step_with_channels(&channels);
+        channels[2] = 2000;
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.signed_duties(), [255, 0, 255, 0]);
+        assert_eq!(bench.serial.take_output(), "drive: tank\r\n");
+    }
+
+    #[test]
+    fn waits_for_the_left_stick_centered_before_driving_in_tank() {
+        let mut bench = Bench::new();
+        bench.serial.send("set drive.mode 3\r\n");
+        bench.run_for(20);
+        bench.arm();
+        // SWC flipped down with the throttle resting at the bottom
+        let mut channels = [1500, 1500, 1000, 1500, 1500, 1500, 1000, 1000, 2000, 1000];
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+        // Once centered the left stick drives its side both ways
+        channels[2] = 1500;
+        bench.step_with_channels(&channels);
+        channels[2] = 1000;
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.signed_duties(), [-255, 0, -255, 0]);
+
+        // Armed again in tank, the throttle must be centered again
+        bench.robot.run_command(Command::Disarm);
+        bench.settle_with_channels(&channels);
+        bench.robot.run_command(Command::Arm);
+        bench.settle_with_channels(&channels);
+        assert!(bench.robot.arming_state.is_armed());
+        assert_eq!(bench.motors.signed_duties(), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn arms_with_a_switch_of_the_channel_map() {
+        let mut bench = Bench::new();
+        bench.serial.send("set arm.switch 1\r\n");
+        bench.run_for(20);
+        // SWA down at power up does not arm, nor does the gesture
+        let mut channels = [1500, 1500, 1000, 2000, 1500, 1500, 2000, 1000, 1000, 1000];
+        bench.settle_with_channels(&channels);
+        bench.settle_with_channels(&channels);
+        assert!(!bench.robot.arming_state.is_armed());
+        // Flipped up then down with the right stick centered
+        channels[3] = 1500;
+        channels[6] = 1000;
+        bench.step_with_channels(&channels);
+        channels[6] = 2000;
+        bench.step_with_channels(&channels);
+        assert!(bench.robot.arming_state.is_armed());
+        assert!(bench.led.is_on());
+
+        channels[6] = 1000;
+        bench.step_with_channels(&channels);
+        assert!(!bench.robot.arming_state.is_armed());
+    }
+
+    #[test]
+    fn limits_the_speed_with_the_gear_and_knob() {
+        let mut bench = Bench::new();
+        bench
+            .serial
+            .send("set gear.switch 1\r\nset speed.knob 1\r\n");
+        bench.run_for(60);
+        bench.arm();
+        // Right stick up, VRA turned right and SWA up: crawl at 30 %
+        let mut channels = [1500, 2000, 1000, 1500, 2000, 1500, 1000, 1000, 1000, 1000];
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.signed_duties(), [76, 76, 76, 76]);
+
+        // SWA down and VRA three quarters right: sport at 74 %
+        channels[4] = 1750;
+        channels[6] = 2000;
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.signed_duties(), [188, 188, 188, 188]);
+        assert_eq!(bench.serial.take_output(), "gear: sport\r\n");
+    }
+
+    #[test]
+    fn brakes_then_coasts_with_the_stop_switch() {
+        let mut bench = Bench::new();
+        bench
+            .serial
+            .send("set stop.switch 3\r\nset stop.brake_ms 100\r\n");
+        bench.run_for(60);
+        bench.arm();
+        // Right stick up with SWC in the middle, then released
+        let mut channels = [1500, 2000, 1000, 1500, 1500, 1500, 1000, 1000, 1500, 1000];
+        bench.settle_with_channels(&channels);
+        channels[1] = 1500;
+        while bench.motors.signed_duties() != [0; 4] {
+            bench.step_with_channels(&channels);
+        }
+        assert_eq!(bench.motors.stop_modes(), [StopMode::Brake; 4]);
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.stop_modes(), [StopMode::Coast; 4]);
+
+        // SWC down always brakes
+        channels[8] = 2000;
+        bench.settle_with_channels(&channels);
+        assert_eq!(bench.motors.stop_modes(), [StopMode::Brake; 4]);
+        assert_eq!(
+            bench.serial.take_output(),
+            "stop: brake then coast\r\nstop: brake\r\n"
+        );
+    }
+
+    #[test]
+    fn sends_telemetry_at_the_configured_rate() {
+        let mut bench = Bench::new();
+        bench.run_for(1000);
+        let frames = bench.take_telemetry();
+        assert_eq!(frames.len(), 10);
+        assert_eq!(frames[0].flags, telemetry::FLAG_FAILSAFE);
+        assert_eq!(frames[0].battery_mv, 7200);
+
+        bench.arm();
+        let mut channels = CENTERED;
+        channels[1] = 2000;
+        bench.battery.set_millivolts(6300);
+        bench.settle_with_frame(channels);
+        bench.robot.control_step(3);
+        let frame = *bench.take_telemetry().last().unwrap();
+        assert_eq!(frame.channels, channels);
+        assert_eq!(frame.vy, 255);
+        assert!(frame.wheels.iter().all(|wheel| *wheel > 0));
+        assert_eq!(frame.overruns, 2);
+        assert_eq!(
+            frame.flags,
+            telemetry::FLAG_ARMED | telemetry::FLAG_BATTERY_LOW
+        );
+    }
+
+    #[test]
+    fn reports_the_frame_counts_of_the_receiver() {
+        let mut bench = Bench::new();
+        bench.step_with_frame(CENTERED);
+        bench.input.lose_frames(3, 2);
+        bench.step_with_frame(CENTERED);
+        bench.serial.send("status\n");
+        bench.run_for(200);
+        assert!(bench
+            .serial
+            .take_output()
+            .contains("frames: 2 good, 3 bad, 2 missed\r\n"));
+
+        bench.step_with_frame(CENTERED);
+        bench.run_for(200);
+        let frame = *bench.take_telemetry().last().unwrap();
+        assert_eq!(
+            frame.frames,
+            FrameStats {
+                good: 3,
+                bad: 3,
+                missed: 2
+            }
+        );
+    }
+}
@@ -1,133 +1,382 @@
-use arduino_hal::Peripherals;
-use avr_device::interrupt::{CriticalSection, Mutex};
-use core::{
-    cell::{Cell, RefCell},
-    iter::Enumerate,
-};
-
-const MICROSECONDS_PER_TICK: u16 = 4;
-const FIRST_CHANNEL_INDEX: u8 = 0;
-const MAX_NUM_CHANNELS: usize = 6;
-const BLANK_PPM_SIGNAL_IN_MILLISECONDS: u16 = 4000;
-
-static LAST_TICK: Mutex<Cell<u16>> = Mutex::new(Cell::new(1));
-static CHANNEL_INDEX: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
-pub static CHANNEL_VALUES: Mutex<RefCell<[u16; MAX_NUM_CHANNELS]>> =
-    Mutex::new(RefCell::new([0; MAX_NUM_CHANNELS]));
+use crate::hal::{FrameStats, RcChannels, MAX_RC_CHANNELS, NUM_STICK_CHANNELS};
 
 pub type PositionValue = u16;
 
-pub struct Ppm {}
+const FIRST_CHANNEL_INDEX: usize = 0;
+pub const MAX_NUM_CHANNELS: usize = MAX_RC_CHANNELS;
+// Frames with fewer channels lack some of the sticks and are not complete
+pub const MIN_NUM_CHANNELS: usize = NUM_STICK_CHANNELS;
+// Pulses at least this long are the sync gap between two frames
+const BLANK_PPM_SIGNAL_IN_MICROSECONDS: u32 = 4000;
+// Range of the pulse of a channel, wider than the 1000 to 2000 µs of the sticks
+const MIN_PULSE_IN_MICROSECONDS: u32 = 800;
+const MAX_PULSE_IN_MICROSECONDS: u32 = 2200;
 
-impl Ppm {
-    /// Initializes PPM reading from digital pin D2.
-    pub fn init_from_d2(ph: &Peripherals) -> Self {
-        ph.TC1.tccr1b.write(|w| w.cs1().prescale_64());
-        ph.EXINT.eicra.write(|w| w.isc0().bits(0b11));
-        ph.EXINT.eimsk.write(|w| w.int0().set_bit());
-        avr_interrupt_enable();
-        Self {}
+/// Decodes a PPM pulse train from the timestamps of its rising edges.
+/// Timestamps are microseconds of the 32 bit clock and may wrap around, so gaps
+/// of up to 71 minutes are measured. Each interval between two edges is the value of a channel,
+/// until an interval of at least 4 ms marks the end of the frame. Frames carry
+/// from 6 to 10 channels.
+///
+/// A frame is only published if all its pulses are within 800 to 2200 µs, it
+/// carries the channel count of the last good frame, and it does not end in
+/// less than half the frame period. Otherwise it is counted as bad and the last
+/// good frame is kept. A new channel count is accepted once two frames in a row
+/// carry it. The frame period is learned from two good frames in a row of about
+/// the same period, and a frame later than one and a half periods counts the
+/// frames missed before it.
+pub struct PpmDecoder {
+    last_edge_us: u32,
+    // None until the first sync gap, so a partial frame is never mislabeled
+    channel_index: Option<usize>,
+    // Channels of the frame being received
+    frame_values: [PositionValue; MAX_NUM_CHANNELS],
+    // False once a pulse of the frame being received is out of range
+    frame_pulses_valid: bool,
+    // Time since the end of the previous frame
+    frame_elapsed_us: u32,
+    // Channels of the last good frame
+    channel_values: [PositionValue; MAX_NUM_CHANNELS],
+    channel_count: usize,
+    // Channel count of the previous frame, good or bad
+    previous_count: usize,
+    // Period of the last good frame, and the period confirmed by two good frames
+    // in a row, 0 until known
+    last_period_us: u32,
+    frame_period_us: u32,
+    stats: FrameStats,
+}
+
+impl PpmDecoder {
+    /// Creates a decoder waiting for the first sync gap.
+    pub const fn new() -> Self {
+        Self {
+            last_edge_us: 0,
+            channel_index: None,
+            frame_values: [0; MAX_NUM_CHANNELS],
+            frame_pulses_valid: true,
+            frame_elapsed_us: 0,
+            channel_values: [0; MAX_NUM_CHANNELS],
+            channel_count: 0,
+            previous_count: 0,
+            last_period_us: 0,
+            frame_period_us: 0,
+            stats: FrameStats {
+                good: 0,
+                bad: 0,
+                missed: 0,
+            },
+        }
     }
 
-    /// Initializes PPM reading from digital pin D3.
-    pub fn init_from_d3(ph: &Peripherals) -> Self {
-        ph.TC1.tccr1b.write(|w| w.cs1().prescale_64());
-        ph.EXINT.eicra.write(|w| w.isc1().bits(0b11));
-        ph.EXINT.eimsk.write(|w| w.int1().set_bit());
-        avr_interrupt_enable();
-        Self {}
+    /// Feeds the time of a rising edge, in microseconds. Returns true if the edge
+    /// ends a good frame, which then replaces the channels.
+    pub fn push_edge(&mut self, now_us: u32) -> bool {
+        let time_elapse_in_microseconds = self.get_microseconds_from_last_interruption(now_us);
+        self.frame_elapsed_us = self
+            .frame_elapsed_us
+            .saturating_add(time_elapse_in_microseconds);
+        if is_reading_channels(time_elapse_in_microseconds) {
+            self.process_channel(time_elapse_in_microseconds);
+            false
+        } else {
+            self.end_frame()
+        }
     }
 
-    /// Returns an iterator over the current PPM channel values.
-    pub fn get_channels(&self) -> Enumerate<core::array::IntoIter<PositionValue, 6>> {
-        avr_device::interrupt::free(|cs| {
-            let chanel_values = CHANNEL_VALUES.borrow(cs).borrow();
-            chanel_values.into_iter().enumerate()
-        })
+    /// Returns the channel values and the channel count of the last good frame.
+    pub fn get_channels(&self) -> RcChannels {
+        RcChannels {
+            values: self.channel_values,
+            count: self.channel_count,
+        }
     }
-}
 
-/// Enables global AVR interrupts.
-fn avr_interrupt_enable() {
-    unsafe {
-        avr_device::interrupt::enable();
+    /// Returns the counts of good, bad and missed frames since start.
+    pub fn get_stats(&self) -> FrameStats {
+        self.stats
     }
-}
 
-#[avr_device::interrupt(atmega328p)]
-fn INT0() {
-    let current_ticks = get_ticks_of_timer_counter_1();
-    avr_device::interrupt::free(|cs| {
-        let time_elapse_in_microseconds =
-            get_microseconds_from_last_interruption(cs, current_ticks);
-        if is_reading_channels(time_elapse_in_microseconds) {
-            process_channel(cs, time_elapse_in_microseconds);
-        } else {
-            reset_channels(cs);
+    /// Calculates microseconds elapsed since the last edge.
+    fn get_microseconds_from_last_interruption(&mut self, now_us: u32) -> u32 {
+        let elapsed_us = now_us.wrapping_sub(self.last_edge_us);
+        self.last_edge_us = now_us;
+        elapsed_us
+    }
+
+    /// Checks the frame that just ended, publishes it if good and starts the next
+    /// one. Returns true if the frame was good.
+    fn end_frame(&mut self) -> bool {
+        let period_us = self.frame_elapsed_us;
+        self.frame_elapsed_us = 0;
+        let pulses_valid = self.frame_pulses_valid;
+        self.frame_pulses_valid = true;
+        let Some(count) = self.channel_index.replace(FIRST_CHANNEL_INDEX) else {
+            // The first sync gap, nothing was received before it
+            return false;
+        };
+
+        let count_expected =
+            self.channel_count == 0 || count == self.channel_count || count == self.previous_count;
+        let count_valid = (MIN_NUM_CHANNELS..=MAX_NUM_CHANNELS).contains(&count) && count_expected;
+        self.previous_count = count;
+        let period_valid = period_us >= self.frame_period_us / 2;
+        if !(pulses_valid && count_valid && period_valid) {
+            self.stats.bad = self.stats.bad.wrapping_add(1);
+            return false;
         }
-    });
-}
 
-#[avr_device::interrupt(atmega328p)]
-fn INT1() {
-    let current_ticks = get_ticks_of_timer_counter_1();
-    avr_device::interrupt::free(|cs| {
-        let time_elapse_in_microseconds =
-            get_microseconds_from_last_interruption(cs, current_ticks);
-        if is_reading_channels(time_elapse_in_microseconds) {
-            process_channel(cs, time_elapse_in_microseconds);
+        // A frame late by more than half a period follows frames never received
+        if self.frame_period_us > 0 && period_us > self.frame_period_us * 3 / 2 {
+            let periods = (period_us + self.frame_period_us / 2) / self.frame_period_us;
+            self.stats.missed = self.stats.missed.wrapping_add(periods - 1);
         } else {
-            reset_channels(cs);
+            if period_us.abs_diff(self.last_period_us) <= self.last_period_us / 4 {
+                self.frame_period_us = period_us;
+            }
+            self.last_period_us = period_us;
         }
-    });
-}
+        self.channel_values[..count].copy_from_slice(&self.frame_values[..count]);
+        self.channel_count = count;
+        self.stats.good = self.stats.good.wrapping_add(1);
+        true
+    }
 
-/// Returns the current value of Timer/Counter1 (TCNT1).
-fn get_ticks_of_timer_counter_1() -> u16 {
-    unsafe { (*avr_device::atmega328p::TC1::ptr()).tcnt1.read().bits() }
+    /// Stores a channel value, checks its range and advances to the next channel.
+    /// Pulses beyond the last channel are counted but not stored.
+    fn process_channel(&mut self, pulse_us: u32) {
+        let Some(id_channel) = self.channel_index else {
+            return;
+        };
+        if !(MIN_PULSE_IN_MICROSECONDS..=MAX_PULSE_IN_MICROSECONDS).contains(&pulse_us) {
+            self.frame_pulses_valid = false;
+        }
+        if id_channel < MAX_NUM_CHANNELS {
+            self.frame_values[id_channel] = pulse_us as PositionValue;
+        }
+        self.channel_index = Some(id_channel.saturating_add(1));
+    }
 }
 
-/// Calculates microseconds elapsed since the last timer interrupt.
-/// 1 Tick every 4 microseconds. Returns elapsed microseconds.
-fn get_microseconds_from_last_interruption(cs: CriticalSection, current_tick: u16) -> u16 {
-    let last_tick = LAST_TICK.borrow(cs).get();
-    LAST_TICK.borrow(cs).set(current_tick);
-    let elapsed_ticks = current_tick.wrapping_sub(last_tick);
-    elapsed_ticks * MICROSECONDS_PER_TICK
+impl Default for PpmDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-/// Resets the channel index to the first channel.
-fn reset_channels(cs: CriticalSection) {
-    CHANNEL_INDEX.borrow(cs).set(FIRST_CHANNEL_INDEX);
+/// Returns true if the elapsed time indicates a valid channel reading.
+fn is_reading_channels(time_elapse_in_microseconds: u32) -> bool {
+    time_elapse_in_microseconds < BLANK_PPM_SIGNAL_IN_MICROSECONDS
 }
 
-/// Advances the channel index to the next channel.
-fn set_next_channel(cs: CriticalSection, id_channel: usize) {
-    if id_channel < MAX_NUM_CHANNELS {
-        CHANNEL_INDEX.borrow(cs).set(id_channel as u8 + 1);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const SYNC_US: u32 = 12000;
+    const CHANNELS: [PositionValue; NUM_STICK_CHANNELS] = [1500, 1000, 2000, 1236, 1752, 1100];
+    const TEN_CHANNELS: [PositionValue; MAX_NUM_CHANNELS] =
+        [1500, 1000, 2000, 1236, 1752, 1100, 1000, 2000, 1500, 1000];
+
+    /// Returns the times of the edges of consecutive pulses, starting at `start_us`.
+    fn edges(start_us: u32, pulses_us: &[u32]) -> Vec<u32> {
+        let mut now_us = start_us;
+        let mut edges = Vec::from([now_us]);
+        for pulse_us in pulses_us {
+            now_us = now_us.wrapping_add(*pulse_us);
+            edges.push(now_us);
+        }
+        edges
     }
-}
 
-/// Returns true if the elapsed time indicates a valid channel reading.
-fn is_reading_channels(time_elapse_in_microseconds: u16) -> bool {
-    time_elapse_in_microseconds < BLANK_PPM_SIGNAL_IN_MILLISECONDS
-}
+    /// Returns the pulses of a sync gap followed by the given channels.
+    fn frame(channels: &[PositionValue]) -> Vec<u32> {
+        let mut pulses = Vec::from([SYNC_US]);
+        pulses.extend(channels.iter().map(|value| *value as u32));
+        pulses
+    }
 
-/// Processes a channel value and advances to the next channel.
-fn process_channel(cs: CriticalSection, value: u16) {
-    let current_id_channel = get_current_channel(cs);
-    set_channel_value(cs, current_id_channel, value);
-    set_next_channel(cs, current_id_channel);
-}
+    /// Feeds the edges and returns how many frames completed.
+    fn replay(decoder: &mut PpmDecoder, edges: &[u32]) -> usize {
+        edges
+            .iter()
+            .filter(|now_us| decoder.push_edge(**now_us))
+            .count()
+    }
 
-/// Returns the current channel index.
-fn get_current_channel(cs: CriticalSection) -> usize {
-    CHANNEL_INDEX.borrow(cs).get() as usize
-}
+    #[test]
+    fn decodes_valid_frames() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend(frame(&CHANNELS));
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(100, &pulses)), 2);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+    }
+
+    #[test]
+    fn ignores_pulses_before_first_sync_gap() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = Vec::from([1300, 1700, 40, 900]);
+        pulses.extend(frame(&CHANNELS));
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 1);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+    }
+
+    #[test]
+    fn truncated_frame_is_bad() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend(frame(&[1900, 1800, 1700, 1600]));
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 1);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+        assert_eq!(decoder.get_stats().bad, 1);
+    }
+
+    #[test]
+    fn rejects_glitches_and_keeps_the_last_good_frame() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&CHANNELS);
+        // A noisy edge splits the second channel in two
+        pulses.extend(frame(&[1500, 300, 700, 2000, 1236, 1752, 1100]));
+        // A pulse too long for a channel
+        pulses.extend(frame(&[1500, 1000, 2600, 1236, 1752, 1100]));
+        pulses.extend(frame(&[1200, 1000, 2000, 1236, 1752, 1100]));
+        pulses.push(SYNC_US);
+
+        let edges = edges(0, &pulses);
+        let (first, second) = edges.split_at(16);
+        assert_eq!(replay(&mut decoder, first), 1);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+        assert_eq!(replay(&mut decoder, second), 1);
+        assert_eq!(decoder.get_channels().values[0], 1200);
+        assert_eq!(
+            decoder.get_stats(),
+            FrameStats {
+                good: 2,
+                bad: 2,
+                missed: 0
+            }
+        );
+    }
+
+    #[test]
+    fn accepts_a_new_channel_count_on_two_frames() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&TEN_CHANNELS);
+        pulses.extend(frame(&TEN_CHANNELS[..8]));
+        pulses.extend(frame(&TEN_CHANNELS[..8]));
+        pulses.push(SYNC_US);
+
+        let edges = edges(0, &pulses);
+        let (first, second) = edges.split_at(22);
+        assert_eq!(replay(&mut decoder, first), 1);
+        assert_eq!(decoder.get_channels().count, 10);
+        assert_eq!(decoder.get_channels().values, TEN_CHANNELS);
+        assert_eq!(replay(&mut decoder, second), 1);
+        assert_eq!(decoder.get_channels().count, 8);
+        assert_eq!(decoder.get_channels().get(8), None);
+        assert_eq!(decoder.get_stats().bad, 1);
+    }
+
+    #[test]
+    fn overlong_frame_is_bad() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&TEN_CHANNELS);
+        pulses.extend(frame(&TEN_CHANNELS));
+        pulses.extend([1200, 1300]);
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 1);
+        assert_eq!(decoder.get_channels().values, TEN_CHANNELS);
+        assert_eq!(decoder.get_stats().bad, 1);
+    }
+
+    #[test]
+    fn decodes_across_clock_wraparound() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&CHANNELS);
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(u32::MAX - 12_000, &pulses)), 1);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+    }
+
+    #[test]
+    fn long_silence_counts_missed_frames() {
+        let mut decoder = PpmDecoder::new();
+        // 100 ms without edges must not overflow into a channel value. The frames
+        // before it set the period to 20.588 ms, so it misses 4 frames
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend(frame(&CHANNELS));
+        pulses.extend(frame(&CHANNELS));
+        pulses.push(100_000);
+        pulses.extend(&frame(&CHANNELS)[1..]);
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 4);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+        assert_eq!(
+            decoder.get_stats(),
+            FrameStats {
+                good: 4,
+                bad: 0,
+                missed: 4
+            }
+        );
+    }
+
+    #[test]
+    fn silence_longer_than_the_timer_wrap_counts_every_missed_frame() {
+        let mut decoder = PpmDecoder::new();
+        // 400 ms is longer than the 262 ms Timer/Counter1 wraps in, which must not
+        // shorten it. 408.588 ms since the previous frame end make 20 periods
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend(frame(&CHANNELS));
+        pulses.extend(frame(&CHANNELS));
+        pulses.push(400_000);
+        pulses.extend(&frame(&CHANNELS)[1..]);
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 4);
+        assert_eq!(decoder.get_stats().missed, 19);
+    }
+
+    #[test]
+    fn rejects_frames_ending_too_early() {
+        let mut decoder = PpmDecoder::new();
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend(frame(&CHANNELS));
+        pulses.extend(frame(&CHANNELS));
+        pulses.push(SYNC_US);
+        // Valid pulses, but 8.8 ms after frames of 20.588 ms
+        pulses.extend([800; 6]);
+        pulses.push(4000);
+        pulses.extend(CHANNELS.map(|value| value as u32));
+        pulses.push(SYNC_US);
+
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 4);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+        assert_eq!(decoder.get_stats().bad, 1);
+    }
+
+    #[test]
+    fn sync_gap_starts_at_four_milliseconds() {
+        let mut decoder = PpmDecoder::new();
+        // 3996 µs is still a channel, too long for one, 4000 µs starts the next frame
+        let mut pulses = frame(&CHANNELS);
+        pulses.extend([3996, 4000]);
+        pulses.extend(CHANNELS.map(|value| value as u32));
+        pulses.push(SYNC_US);
 
-/// Sets the value for the specified channel index.
-fn set_channel_value(cs: CriticalSection, id_channel: usize, value: u16) {
-    if id_channel < MAX_NUM_CHANNELS {
-        CHANNEL_VALUES.borrow(cs).borrow_mut()[id_channel] = value;
+        assert_eq!(replay(&mut decoder, &edges(0, &pulses)), 1);
+        assert_eq!(decoder.get_channels().values[..6], CHANNELS);
+        assert_eq!(decoder.get_stats().bad, 1);
     }
 }
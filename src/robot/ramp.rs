@@ -0,0 +1,121 @@
+use crate::robot::mecanum::WheelOutputs;
+
+/// Slew rate limits of the wheel duty, in duty units per control step.
+/// At 50 Hz a limit of 10 reaches full scale in about half a second.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RampLimits {
+    /// Largest increase of the speed of a wheel in one step.
+    pub accel: u8,
+    /// Largest decrease of the speed of a wheel in one step.
+    pub decel: u8,
+}
+
+impl Default for RampLimits {
+    fn default() -> Self {
+        Self {
+            accel: 10,
+            decel: 20,
+        }
+    }
+}
+
+/// Limits how fast the duty of each wheel changes between control steps.
+#[derive(Default)]
+pub struct WheelRamp {
+    current: [i16; 4],
+}
+
+impl WheelRamp {
+    /// Moves every wheel from its current duty towards `target` within `limits`
+    /// and returns the duties to apply.
+    pub fn apply(&mut self, target: WheelOutputs, limits: RampLimits) -> WheelOutputs {
+        let WheelOutputs { a, b, c, d } = target;
+        for (current, target) in self.current.iter_mut().zip([a, b, c, d]) {
+            *current = step(*current, target, limits);
+        }
+        let [a, b, c, d] = self.current;
+        WheelOutputs { a, b, c, d }
+    }
+
+    /// Forgets the current duties, after the motors were stopped outside the ramp.
+    pub fn reset(&mut self) {
+        self.current = [0; 4];
+    }
+}
+
+/// Returns the next duty from `current` towards `target`.
+/// Slowing down uses the decel limit and speeding up the accel limit. A change of
+/// direction first brakes down to zero and only accelerates the other way on the
+/// following steps, so the motor never jumps across zero.
+fn step(current: i16, target: i16, limits: RampLimits) -> i16 {
+    let accel = limits.accel.max(1) as i16;
+    let decel = limits.decel.max(1) as i16;
+
+    if current != 0 && (current > 0) != (target > 0) {
+        // Sentido contrario o parada: frenar sin cruzar el cero
+        return if current > 0 {
+            (current - decel).max(0.max(target))
+        } else {
+            (current + decel).min(0.min(target))
+        };
+    }
+
+    if target.abs() > current.abs() {
+        if target > current {
+            (current + accel).min(target)
+        } else {
+            (current - accel).max(target)
+        }
+    } else if target > current {
+        (current + decel).min(target)
+    } else {
+        (current - decel).max(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: RampLimits = RampLimits {
+        accel: 10,
+        decel: 50,
+    };
+
+    /// Returns the duties a wheel goes through until it settles on `target`.
+    fn trajectory(mut current: i16, target: i16) -> std::vec::Vec<i16> {
+        let mut duties = std::vec::Vec::new();
+        while current != target {
+            current = step(current, target, LIMITS);
+            duties.push(current);
+        }
+        duties
+    }
+
+    #[test]
+    fn accelerates_and_decelerates_at_their_limits() {
+        assert_eq!(trajectory(0, 35), [10, 20, 30, 35]);
+        assert_eq!(trajectory(0, -25), [-10, -20, -25]);
+        assert_eq!(trajectory(200, 60), [150, 100, 60]);
+        assert_eq!(trajectory(-120, 0), [-70, -20, 0]);
+    }
+
+    #[test]
+    fn reversal_stops_at_zero_before_changing_direction() {
+        assert_eq!(trajectory(100, -20), [50, 0, -10, -20]);
+        assert_eq!(trajectory(-30, 15), [0, 10, 15]);
+    }
+
+    #[test]
+    fn ramps_each_wheel_independently() {
+        let mut ramp = WheelRamp::default();
+        let target = WheelOutputs {
+            a: 255,
+            b: -255,
+            c: 5,
+            d: 0,
+        };
+        let WheelOutputs { a, b, c, d } = ramp.apply(target, LIMITS);
+        assert_eq!([a, b, c, d], [10, -10, 5, 0]);
+    }
+}
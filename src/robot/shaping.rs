@@ -0,0 +1,138 @@
+use crate::robot::{
+    calibration::ChannelCalibration, helper, helper::MAX_POTENCY, ppm::PositionValue,
+};
+
+/// Shaping of one stick axis, applied to the raw channel value before the mixer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AxisShape {
+    /// Half width in microseconds of the band around the center that reads as zero.
+    pub deadzone: u16,
+    /// Blend between a linear (0) and a cubic (100) response, in percent.
+    /// Softens the center for fine positioning while keeping full speed at the edges.
+    pub expo: u8,
+    /// Output at full deflection, in percent of full scale.
+    pub rate: u8,
+    /// Inverts the direction of the axis.
+    pub reverse: bool,
+}
+
+impl AxisShape {
+    pub const MAX_EXPO: u8 = 100;
+    pub const MAX_RATE: u8 = 100;
+
+    /// Maps a channel value to a signed potency in -255..=255 through the
+    /// calibrated range of the channel and the deadzone, expo, rate and reverse
+    /// stages. Integer only, the ATmega328p has no FPU.
+    pub fn apply(&self, value: PositionValue, calibration: &ChannelCalibration) -> i16 {
+        let full_scale_squared = MAX_POTENCY as i32 * MAX_POTENCY as i32;
+        let linear = helper::map_u16_to_i16(value, self.deadzone, calibration) as i32;
+
+        // Blend of the linear and cubic responses, in hundredths of potency
+        let expo = self.expo.min(Self::MAX_EXPO) as i32;
+        let cubic = linear * linear * linear;
+        let curved =
+            (linear * (100 - expo) * full_scale_squared + cubic * expo) / full_scale_squared;
+
+        let rate = self.rate.min(Self::MAX_RATE) as i32;
+        let shaped = (curved * rate / (100 * 100)) as i16;
+
+        if self.reverse {
+            -shaped
+        } else {
+            shaped
+        }
+    }
+}
+
+impl Default for AxisShape {
+    /// Returns a linear axis with the 50 µs deadzone and full rate.
+    fn default() -> Self {
+        Self {
+            deadzone: 50,
+            expo: 0,
+            rate: Self::MAX_RATE,
+            reverse: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOMINAL: ChannelCalibration = ChannelCalibration::NOMINAL;
+
+    /// Floating point shaping the integer version must reproduce, starting
+    /// from the floating point mapping of the channel value.
+    fn apply_reference(shape: &AxisShape, value: PositionValue) -> i16 {
+        let mapped = helper::tests::map_u16_to_i16_reference(value, shape.deadzone, &NOMINAL);
+        let linear = mapped as f32 / 255.0;
+        let expo = shape.expo.min(AxisShape::MAX_EXPO) as f32 / 100.0;
+        let curved = linear * (1.0 - expo) + linear * linear * linear * expo;
+        let rate = shape.rate.min(AxisShape::MAX_RATE) as f32 / 100.0;
+        let shaped = (curved * rate * 255.0) as i16;
+        if shape.reverse {
+            -shaped
+        } else {
+            shaped
+        }
+    }
+
+    #[test]
+    fn integer_shaping_matches_float_reference() {
+        for deadzone in [0, 50] {
+            for expo in (0..=AxisShape::MAX_EXPO).step_by(5) {
+                for rate in (0..=AxisShape::MAX_RATE).step_by(5) {
+                    for reverse in [false, true] {
+                        let shape = AxisShape {
+                            deadzone,
+                            expo,
+                            rate,
+                            reverse,
+                        };
+                        for value in 1000..=2000 {
+                            let shaped = shape.apply(value, &NOMINAL);
+                            let reference = apply_reference(&shape, value);
+                            assert!(
+                                shaped.abs_diff(reference) <= 1,
+                                "{shape:?} value {value}: {shaped} != {reference}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn default_is_linear_with_deadzone() {
+        let shape = AxisShape::default();
+        assert_eq!(shape.apply(1540, &NOMINAL), 0);
+        assert_eq!(shape.apply(1460, &NOMINAL), 0);
+        assert_eq!(shape.apply(2000, &NOMINAL), 255);
+        assert_eq!(shape.apply(1000, &NOMINAL), -255);
+        assert_eq!(shape.apply(1775, &NOMINAL), 127);
+    }
+
+    #[test]
+    fn expo_softens_the_center_but_keeps_the_ends() {
+        let shape = AxisShape {
+            expo: 100,
+            ..AxisShape::default()
+        };
+        assert_eq!(shape.apply(1775, &NOMINAL), 31);
+        assert_eq!(shape.apply(2000, &NOMINAL), 255);
+        assert_eq!(shape.apply(1000, &NOMINAL), -255);
+    }
+
+    #[test]
+    fn rate_and_reverse_scale_the_output() {
+        let shape = AxisShape {
+            rate: 50,
+            reverse: true,
+            ..AxisShape::default()
+        };
+        assert_eq!(shape.apply(2000, &NOMINAL), -127);
+        assert_eq!(shape.apply(1000, &NOMINAL), 127);
+    }
+}
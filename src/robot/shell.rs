@@ -0,0 +1,682 @@
+use crate::hal::{MotorId, MAX_RC_CHANNELS, NUM_STICK_CHANNELS};
+use crate::robot::arming::ArmMode;
+use crate::robot::config::Config;
+use crate::robot::deadband::MAX_MIN_DUTY;
+use crate::robot::flysky::channel_map::ChannelFunction;
+use crate::robot::kinematics::DriveMode;
+use crate::robot::speed::Gear;
+use crate::robot::stopping::StopStyle;
+use crate::robot::wheels::Wheel;
+use ufmt::uWrite;
+
+// Longest command line, longer lines are rejected whole
+pub const MAX_LINE_LEN: usize = 48;
+// Bytes that erase the previous one in a terminal
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+pub const HELP: &str = "commands:\r\n\
+  help                      this list\r\n\
+  status                    state, battery, loop timing and frame counts\r\n\
+  get [key]                 show one or all settings\r\n\
+  set <key> <value>         change a setting until restart\r\n\
+  save                      keep the settings after a restart\r\n\
+  calibrate                 record the ends and centers of the sticks\r\n\
+  motors test <a-d> <duty>  run a motor for a second while disarmed\r\n\
+  motors start <a-d>        speed a motor up until a key is pressed\r\n\
+  wheels test <duty>        run each wheel in turn for a second while disarmed\r\n\
+  arm, disarm\r\n";
+
+/// Reasons a command line is rejected.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShellError {
+    LineTooLong,
+    UnknownCommand,
+    MissingArgument,
+    UnknownSetting,
+    InvalidValue,
+    OutOfRange,
+    UnknownMotor,
+}
+
+impl ShellError {
+    /// Returns the message reported on serial.
+    pub fn message(self) -> &'static str {
+        match self {
+            ShellError::LineTooLong => "line too long",
+            ShellError::UnknownCommand => "unknown command, try help",
+            ShellError::MissingArgument => "missing argument",
+            ShellError::UnknownSetting => "unknown setting, try get",
+            ShellError::InvalidValue => "invalid value",
+            ShellError::OutOfRange => "value out of range",
+            ShellError::UnknownMotor => "unknown motor, use a, b, c or d",
+        }
+    }
+}
+
+/// Accumulates received bytes into command lines.
+pub struct LineBuffer {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+    overflow: bool,
+}
+
+/// A complete command line, without its terminator.
+#[derive(Clone, Copy)]
+pub struct Line {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+}
+
+impl Line {
+    /// Returns the text of the line, empty if it is not valid UTF-8.
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_LINE_LEN],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Feeds a received byte. Returns the line when the byte ends a non empty one.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Line, ShellError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let line = Line {
+                    bytes: self.bytes,
+                    len: self.len,
+                };
+                let overflow = self.overflow;
+                self.len = 0;
+                self.overflow = false;
+                if overflow {
+                    Some(Err(ShellError::LineTooLong))
+                } else if line.len == 0 {
+                    None
+                } else {
+                    Some(Ok(line))
+                }
+            }
+            BACKSPACE | DELETE => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len == MAX_LINE_LEN => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Commands of the serial shell.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Help,
+    Status,
+    /// Shows one setting, or all of them.
+    Get(Option<Setting>),
+    Set(Setting, i32),
+    Save,
+    /// Runs a motor at a signed duty for a moment.
+    MotorTest(MotorId, i16),
+    /// Raises the duty of a motor until the pilot confirms it turns, to find its
+    /// minimum duty.
+    MotorStart(MotorId),
+    /// Runs each wheel in turn at a signed duty, to check the wheel map.
+    WheelTest(i16),
+    /// Starts the stick calibration routine.
+    Calibrate,
+    Arm,
+    Disarm,
+}
+
+/// Parses a command line.
+pub fn parse(line: &str) -> Result<Command, ShellError> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some("help") => Command::Help,
+        Some("status") => Command::Status,
+        Some("get") => Command::Get(words.next().map(parse_setting).transpose()?),
+        Some("set") => {
+            let setting = parse_setting(words.next().ok_or(ShellError::MissingArgument)?)?;
+            let value = parse_value(words.next().ok_or(ShellError::MissingArgument)?)?;
+            Command::Set(setting, value)
+        }
+        Some("save") => Command::Save,
+        Some("motors") => {
+            let test = match words.next() {
+                Some("test") => true,
+                Some("start") => false,
+                _ => return Err(ShellError::UnknownCommand),
+            };
+            let id = parse_motor(words.next().ok_or(ShellError::MissingArgument)?)?;
+            if !test {
+                Command::MotorStart(id)
+            } else {
+                let duty = parse_value(words.next().ok_or(ShellError::MissingArgument)?)?;
+                if !(-255..=255).contains(&duty) {
+                    return Err(ShellError::OutOfRange);
+                }
+                Command::MotorTest(id, duty as i16)
+            }
+        }
+        Some("wheels") if words.next() == Some("test") => {
+            let duty = parse_value(words.next().ok_or(ShellError::MissingArgument)?)?;
+            if !(-255..=255).contains(&duty) {
+                return Err(ShellError::OutOfRange);
+            }
+            Command::WheelTest(duty as i16)
+        }
+        Some("calibrate") => Command::Calibrate,
+        Some("arm") => Command::Arm,
+        Some("disarm") => Command::Disarm,
+        _ => return Err(ShellError::UnknownCommand),
+    };
+    if words.next().is_some() {
+        return Err(ShellError::InvalidValue);
+    }
+    Ok(command)
+}
+
+/// Parses a number, or a boolean as 1 and 0.
+fn parse_value(word: &str) -> Result<i32, ShellError> {
+    match word {
+        "true" | "on" => Ok(1),
+        "false" | "off" => Ok(0),
+        _ => word.parse().map_err(|_| ShellError::InvalidValue),
+    }
+}
+
+fn parse_motor(word: &str) -> Result<MotorId, ShellError> {
+    match word {
+        "a" => Ok(MotorId::A),
+        "b" => Ok(MotorId::B),
+        "c" => Ok(MotorId::C),
+        "d" => Ok(MotorId::D),
+        _ => Err(ShellError::UnknownMotor),
+    }
+}
+
+/// Returns the letter of a motor in the shell.
+pub fn motor_name(id: MotorId) -> &'static str {
+    match id {
+        MotorId::A => "a",
+        MotorId::B => "b",
+        MotorId::C => "c",
+        MotorId::D => "d",
+    }
+}
+
+/// Tunable values of the `Config`, named like `ch1.rate` or `wheel.fl.invert`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Setting {
+    Deadzone(usize),
+    Expo(usize),
+    Rate(usize),
+    Reverse(usize),
+    /// Calibrated ends and center of a channel.
+    Min(usize),
+    Center(usize),
+    Max(usize),
+    /// Distance from the middle a stick reads centered within.
+    CenterBand,
+    /// Receiver channel a function is read from, and whether it is reversed.
+    MapChannel(ChannelFunction),
+    MapReverse(ChannelFunction),
+    /// Number of the `DriveMode`.
+    DriveMode,
+    /// Whether VRA limits the speed.
+    SpeedKnob,
+    /// Number of the switch selecting the gear, 0 for none.
+    GearSwitch,
+    /// Speed of a gear in percent.
+    GearPercent(Gear),
+    /// Number of the `StopStyle` used without a switch.
+    StopStyle,
+    /// Number of the switch selecting the stop style, 0 for none.
+    StopSwitch,
+    StopBrakeTime,
+    RampAccel,
+    RampDecel,
+    /// Duty a motor starts turning at.
+    MinDuty(MotorId),
+    /// Motor driving a wheel, 0 to 3 for A to D.
+    WheelMotor(Wheel),
+    WheelInvert(Wheel),
+    /// Number of the switch arming the robot, 0 for the stick gesture.
+    ArmSwitch,
+    FailsafeTimeout,
+    ControlRate,
+    BatteryWarning,
+    BatteryCutoff,
+    BatteryMinPower,
+    TelemetryRate,
+}
+
+fn parse_setting(key: &str) -> Result<Setting, ShellError> {
+    if let Some((channel, field)) = key.strip_prefix("ch").and_then(|rest| rest.split_once('.')) {
+        let channel = channel
+            .parse()
+            .ok()
+            .filter(|channel| *channel < NUM_STICK_CHANNELS)
+            .ok_or(ShellError::UnknownSetting)?;
+        return match field {
+            "deadzone" => Ok(Setting::Deadzone(channel)),
+            "expo" => Ok(Setting::Expo(channel)),
+            "rate" => Ok(Setting::Rate(channel)),
+            "reverse" => Ok(Setting::Reverse(channel)),
+            "min" => Ok(Setting::Min(channel)),
+            "center" => Ok(Setting::Center(channel)),
+            "max" => Ok(Setting::Max(channel)),
+            _ => Err(ShellError::UnknownSetting),
+        };
+    }
+    if let Some(rest) = key.strip_prefix("map.") {
+        let (name, reverse) = match rest.strip_suffix(".reverse") {
+            Some(name) => (name, true),
+            None => (rest, false),
+        };
+        let function = ChannelFunction::from_name(name).ok_or(ShellError::UnknownSetting)?;
+        return Ok(if reverse {
+            Setting::MapReverse(function)
+        } else {
+            Setting::MapChannel(function)
+        });
+    }
+    if let Some(motor) = key
+        .strip_prefix("motor.")
+        .and_then(|rest| rest.strip_suffix(".min_duty"))
+    {
+        return parse_motor(motor).map(Setting::MinDuty);
+    }
+    if let Some((name, field)) = key
+        .strip_prefix("wheel.")
+        .and_then(|rest| rest.split_once('.'))
+    {
+        let wheel = Wheel::ALL
+            .into_iter()
+            .find(|wheel| wheel.name() == name)
+            .ok_or(ShellError::UnknownSetting)?;
+        return match field {
+            "motor" => Ok(Setting::WheelMotor(wheel)),
+            "invert" => Ok(Setting::WheelInvert(wheel)),
+            _ => Err(ShellError::UnknownSetting),
+        };
+    }
+    if let Some(gear) = key
+        .strip_prefix("gear.")
+        .and_then(|name| Gear::ALL.into_iter().find(|gear| gear.name() == name))
+    {
+        return Ok(Setting::GearPercent(gear));
+    }
+    match key {
+        "stick.center_band" => Ok(Setting::CenterBand),
+        "drive.mode" => Ok(Setting::DriveMode),
+        "speed.knob" => Ok(Setting::SpeedKnob),
+        "gear.switch" => Ok(Setting::GearSwitch),
+        "stop.style" => Ok(Setting::StopStyle),
+        "stop.switch" => Ok(Setting::StopSwitch),
+        "stop.brake_ms" => Ok(Setting::StopBrakeTime),
+        "ramp.accel" => Ok(Setting::RampAccel),
+        "ramp.decel" => Ok(Setting::RampDecel),
+        "arm.switch" => Ok(Setting::ArmSwitch),
+        "failsafe.timeout_ms" => Ok(Setting::FailsafeTimeout),
+        "control.rate" => Ok(Setting::ControlRate),
+        "battery.warning" => Ok(Setting::BatteryWarning),
+        "battery.cutoff" => Ok(Setting::BatteryCutoff),
+        "battery.min_power" => Ok(Setting::BatteryMinPower),
+        "telemetry.rate" => Ok(Setting::TelemetryRate),
+        _ => Err(ShellError::UnknownSetting),
+    }
+}
+
+impl Setting {
+    /// Returns every setting, in the order `get` lists them.
+    pub fn all() -> impl Iterator<Item = Setting> {
+        (0..NUM_STICK_CHANNELS)
+            .flat_map(|channel| {
+                [
+                    Setting::Deadzone(channel),
+                    Setting::Expo(channel),
+                    Setting::Rate(channel),
+                    Setting::Reverse(channel),
+                    Setting::Min(channel),
+                    Setting::Center(channel),
+                    Setting::Max(channel),
+                ]
+            })
+            .chain([Setting::CenterBand])
+            .chain(ChannelFunction::ALL.into_iter().flat_map(|function| {
+                [Setting::MapChannel(function), Setting::MapReverse(function)]
+            }))
+            .chain([Setting::DriveMode, Setting::SpeedKnob, Setting::GearSwitch])
+            .chain(Gear::ALL.map(Setting::GearPercent))
+            .chain([
+                Setting::StopStyle,
+                Setting::StopSwitch,
+                Setting::StopBrakeTime,
+            ])
+            .chain([Setting::RampAccel, Setting::RampDecel])
+            .chain(MotorId::ALL.map(Setting::MinDuty))
+            .chain(
+                Wheel::ALL
+                    .into_iter()
+                    .flat_map(|wheel| [Setting::WheelMotor(wheel), Setting::WheelInvert(wheel)]),
+            )
+            .chain([
+                Setting::ArmSwitch,
+                Setting::FailsafeTimeout,
+                Setting::ControlRate,
+                Setting::BatteryWarning,
+                Setting::BatteryCutoff,
+                Setting::BatteryMinPower,
+                Setting::TelemetryRate,
+            ])
+    }
+
+    /// Writes the key of the setting.
+    pub fn write_key<W: uWrite>(self, w: &mut W) -> Result<(), W::Error> {
+        match self {
+            Setting::Deadzone(channel) => ufmt::uwrite!(w, "ch{}.deadzone", channel),
+            Setting::Expo(channel) => ufmt::uwrite!(w, "ch{}.expo", channel),
+            Setting::Rate(channel) => ufmt::uwrite!(w, "ch{}.rate", channel),
+            Setting::Reverse(channel) => ufmt::uwrite!(w, "ch{}.reverse", channel),
+            Setting::Min(channel) => ufmt::uwrite!(w, "ch{}.min", channel),
+            Setting::Center(channel) => ufmt::uwrite!(w, "ch{}.center", channel),
+            Setting::Max(channel) => ufmt::uwrite!(w, "ch{}.max", channel),
+            Setting::CenterBand => w.write_str("stick.center_band"),
+            Setting::MapChannel(function) => ufmt::uwrite!(w, "map.{}", function.name()),
+            Setting::MapReverse(function) => {
+                ufmt::uwrite!(w, "map.{}.reverse", function.name())
+            }
+            Setting::DriveMode => w.write_str("drive.mode"),
+            Setting::SpeedKnob => w.write_str("speed.knob"),
+            Setting::GearSwitch => w.write_str("gear.switch"),
+            Setting::GearPercent(gear) => ufmt::uwrite!(w, "gear.{}", gear.name()),
+            Setting::StopStyle => w.write_str("stop.style"),
+            Setting::StopSwitch => w.write_str("stop.switch"),
+            Setting::StopBrakeTime => w.write_str("stop.brake_ms"),
+            Setting::RampAccel => w.write_str("ramp.accel"),
+            Setting::RampDecel => w.write_str("ramp.decel"),
+            Setting::MinDuty(id) => ufmt::uwrite!(w, "motor.{}.min_duty", motor_name(id)),
+            Setting::WheelMotor(wheel) => ufmt::uwrite!(w, "wheel.{}.motor", wheel.name()),
+            Setting::WheelInvert(wheel) => ufmt::uwrite!(w, "wheel.{}.invert", wheel.name()),
+            Setting::ArmSwitch => w.write_str("arm.switch"),
+            Setting::FailsafeTimeout => w.write_str("failsafe.timeout_ms"),
+            Setting::ControlRate => w.write_str("control.rate"),
+            Setting::BatteryWarning => w.write_str("battery.warning"),
+            Setting::BatteryCutoff => w.write_str("battery.cutoff"),
+            Setting::BatteryMinPower => w.write_str("battery.min_power"),
+            Setting::TelemetryRate => w.write_str("telemetry.rate"),
+        }
+    }
+
+    /// Returns the current value of the setting in `config`, booleans as 1 and 0.
+    pub fn get(self, config: &Config) -> i32 {
+        match self {
+            Setting::Deadzone(channel) => config.axes[channel].deadzone as i32,
+            Setting::Expo(channel) => config.axes[channel].expo as i32,
+            Setting::Rate(channel) => config.axes[channel].rate as i32,
+            Setting::Reverse(channel) => config.axes[channel].reverse as i32,
+            Setting::Min(channel) => config.calibration[channel].min as i32,
+            Setting::Center(channel) => config.calibration[channel].center as i32,
+            Setting::Max(channel) => config.calibration[channel].max as i32,
+            Setting::CenterBand => config.center_band as i32,
+            Setting::MapChannel(function) => config.channel_map.source(function).channel as i32,
+            Setting::MapReverse(function) => config.channel_map.source(function).reverse as i32,
+            Setting::DriveMode => config.drive.code() as i32,
+            Setting::SpeedKnob => config.speed.knob as i32,
+            Setting::GearSwitch => config.speed.gear_switch_code() as i32,
+            Setting::GearPercent(gear) => config.speed.gear_percent[gear as usize] as i32,
+            Setting::StopStyle => config.stop.style.code() as i32,
+            Setting::StopSwitch => config.stop.switch_code() as i32,
+            Setting::StopBrakeTime => config.stop.brake_ms as i32,
+            Setting::RampAccel => config.ramp.accel as i32,
+            Setting::RampDecel => config.ramp.decel as i32,
+            Setting::MinDuty(id) => config.motor_min_duty[id as usize] as i32,
+            Setting::WheelMotor(wheel) => config.wheels.motors[wheel as usize] as i32,
+            Setting::WheelInvert(wheel) => config.wheels.invert[wheel as usize] as i32,
+            Setting::ArmSwitch => config.arming.switch_code() as i32,
+            Setting::FailsafeTimeout => config.failsafe_timeout_ms as i32,
+            Setting::ControlRate => config.control_rate_hz as i32,
+            Setting::BatteryWarning => config.battery.warning_mv as i32,
+            Setting::BatteryCutoff => config.battery.cutoff_mv as i32,
+            Setting::BatteryMinPower => config.battery.min_power as i32,
+            Setting::TelemetryRate => config.telemetry_rate_hz as i32,
+        }
+    }
+
+    /// Changes the setting in `config`, if `value` is in its range. The center of
+    /// a channel must stay far enough from both of its ends.
+    pub fn set(self, config: &mut Config, value: i32) -> Result<(), ShellError> {
+        let (min, max) = self.range();
+        if !(min..=max).contains(&value) {
+            return Err(ShellError::OutOfRange);
+        }
+        let mut changed = *config;
+        match self {
+            Setting::Deadzone(channel) => changed.axes[channel].deadzone = value as u16,
+            Setting::Expo(channel) => changed.axes[channel].expo = value as u8,
+            Setting::Rate(channel) => changed.axes[channel].rate = value as u8,
+            Setting::Reverse(channel) => changed.axes[channel].reverse = value != 0,
+            Setting::Min(channel) => changed.calibration[channel].min = value as u16,
+            Setting::Center(channel) => changed.calibration[channel].center = value as u16,
+            Setting::Max(channel) => changed.calibration[channel].max = value as u16,
+            Setting::CenterBand => changed.center_band = value as u16,
+            Setting::MapChannel(function) => {
+                changed.channel_map.sources[function as usize].channel = value as u8
+            }
+            Setting::MapReverse(function) => {
+                changed.channel_map.sources[function as usize].reverse = value != 0
+            }
+            Setting::DriveMode => {
+                changed.drive = DriveMode::from_code(value as u8).ok_or(ShellError::OutOfRange)?
+            }
+            Setting::SpeedKnob => changed.speed.knob = value != 0,
+            Setting::GearSwitch => {
+                if !changed.speed.set_gear_switch_code(value as u8) {
+                    return Err(ShellError::OutOfRange);
+                }
+            }
+            Setting::GearPercent(gear) => changed.speed.gear_percent[gear as usize] = value as u8,
+            Setting::StopStyle => {
+                changed.stop.style =
+                    StopStyle::from_code(value as u8).ok_or(ShellError::OutOfRange)?
+            }
+            Setting::StopSwitch => {
+                if !changed.stop.set_switch_code(value as u8) {
+                    return Err(ShellError::OutOfRange);
+                }
+            }
+            Setting::StopBrakeTime => changed.stop.brake_ms = value as u16,
+            Setting::RampAccel => changed.ramp.accel = value as u8,
+            Setting::RampDecel => changed.ramp.decel = value as u8,
+            Setting::MinDuty(id) => changed.motor_min_duty[id as usize] = value as u8,
+            Setting::WheelMotor(wheel) => {
+                changed.wheels.assign(wheel, MotorId::ALL[value as usize])
+            }
+            Setting::WheelInvert(wheel) => changed.wheels.invert[wheel as usize] = value != 0,
+            Setting::ArmSwitch => {
+                changed.arming =
+                    ArmMode::from_switch_code(value as u8).ok_or(ShellError::OutOfRange)?
+            }
+            Setting::FailsafeTimeout => changed.failsafe_timeout_ms = value as u16,
+            Setting::ControlRate => changed.control_rate_hz = value as u8,
+            Setting::BatteryWarning => changed.battery.warning_mv = value as u16,
+            Setting::BatteryCutoff => changed.battery.cutoff_mv = value as u16,
+            Setting::BatteryMinPower => changed.battery.min_power = value as u8,
+            Setting::TelemetryRate => changed.telemetry_rate_hz = value as u8,
+        }
+        if !self.is_valid(&changed) {
+            return Err(ShellError::OutOfRange);
+        }
+        *config = changed;
+        Ok(())
+    }
+
+    /// Returns true if the value of the setting in `config` is in its range.
+    pub fn is_valid(self, config: &Config) -> bool {
+        let (min, max) = self.range();
+        let calibrated = match self {
+            Setting::Min(channel) | Setting::Center(channel) | Setting::Max(channel) => {
+                config.calibration[channel].is_valid()
+            }
+            Setting::WheelMotor(_) => config.wheels.is_valid(),
+            _ => true,
+        };
+        (min..=max).contains(&self.get(config)) && calibrated
+    }
+
+    /// Returns the smallest and largest accepted values.
+    fn range(self) -> (i32, i32) {
+        match self {
+            Setting::Deadzone(_) => (0, 500),
+            Setting::Min(_) | Setting::Center(_) | Setting::Max(_) => (800, 2200),
+            Setting::Expo(_)
+            | Setting::Rate(_)
+            | Setting::GearPercent(_)
+            | Setting::BatteryMinPower => (0, 100),
+            Setting::Reverse(_)
+            | Setting::MapReverse(_)
+            | Setting::SpeedKnob
+            | Setting::WheelInvert(_) => (0, 1),
+            Setting::MapChannel(_) => (0, MAX_RC_CHANNELS as i32 - 1),
+            Setting::DriveMode | Setting::WheelMotor(_) => (0, 3),
+            Setting::MinDuty(_) => (0, MAX_MIN_DUTY as i32),
+            Setting::GearSwitch | Setting::StopSwitch | Setting::ArmSwitch => (0, 4),
+            Setting::StopStyle => (0, 2),
+            Setting::StopBrakeTime => (0, 2000),
+            Setting::CenterBand => (10, 200),
+            Setting::FailsafeTimeout => (50, 2000),
+            Setting::ControlRate => (4, 100),
+            Setting::RampAccel | Setting::RampDecel => (1, 255),
+            Setting::BatteryWarning | Setting::BatteryCutoff => (0, 20_000),
+            Setting::TelemetryRate => (0, 50),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::robot::flysky::channel_map::NUM_CHANNEL_FUNCTIONS;
+
+    fn feed(buffer: &mut LineBuffer, text: &str) -> std::vec::Vec<Result<Line, ShellError>> {
+        text.bytes().filter_map(|byte| buffer.push(byte)).collect()
+    }
+
+    #[test]
+    fn splits_lines_and_handles_backspace() {
+        let mut buffer = LineBuffer::new();
+        let lines = feed(&mut buffer, "stat\x08\x08atus\r\n\r\nhelp\n");
+        let lines: std::vec::Vec<&str> = lines
+            .iter()
+            .map(|line| line.as_ref().unwrap().as_str())
+            .collect();
+        assert_eq!(lines, ["status", "help"]);
+    }
+
+    #[test]
+    fn rejects_overlong_lines_whole() {
+        let mut buffer = LineBuffer::new();
+        let long = "x".repeat(MAX_LINE_LEN + 1);
+        assert!(feed(&mut buffer, &long).is_empty());
+        assert!(matches!(
+            buffer.push(b'\n'),
+            Some(Err(ShellError::LineTooLong))
+        ));
+        let lines = feed(&mut buffer, "arm\n");
+        assert_eq!(lines[0].as_ref().unwrap().as_str(), "arm");
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse("set ch1.rate 80"),
+            Ok(Command::Set(Setting::Rate(1), 80))
+        );
+        assert_eq!(
+            parse("set wheel.rl.invert true"),
+            Ok(Command::Set(Setting::WheelInvert(Wheel::RearLeft), 1))
+        );
+        assert_eq!(
+            parse("get wheel.fr.motor"),
+            Ok(Command::Get(Some(Setting::WheelMotor(Wheel::FrontRight))))
+        );
+        assert_eq!(parse("wheels test 90"), Ok(Command::WheelTest(90)));
+        assert_eq!(parse("motors start d"), Ok(Command::MotorStart(MotorId::D)));
+        assert_eq!(
+            parse("set motor.b.min_duty 70"),
+            Ok(Command::Set(Setting::MinDuty(MotorId::B), 70))
+        );
+        assert_eq!(
+            parse("set map.turn.reverse on"),
+            Ok(Command::Set(Setting::MapReverse(ChannelFunction::Turn), 1))
+        );
+        assert_eq!(
+            parse("get map.throttle"),
+            Ok(Command::Get(Some(Setting::MapChannel(
+                ChannelFunction::Throttle
+            ))))
+        );
+        assert_eq!(parse("get"), Ok(Command::Get(None)));
+        assert_eq!(
+            parse("  motors test a -120 "),
+            Ok(Command::MotorTest(MotorId::A, -120))
+        );
+        assert_eq!(parse("motors test e 10"), Err(ShellError::UnknownMotor));
+        assert_eq!(parse("motors test a 300"), Err(ShellError::OutOfRange));
+        assert_eq!(parse("set ch6.rate 80"), Err(ShellError::UnknownSetting));
+        assert_eq!(parse("set ramp.accel"), Err(ShellError::MissingArgument));
+        assert_eq!(parse("fly"), Err(ShellError::UnknownCommand));
+    }
+
+    #[test]
+    fn sets_settings_within_their_range() {
+        let mut config = Config::default();
+        assert_eq!(Setting::Deadzone(3).set(&mut config, 30), Ok(()));
+        assert_eq!(config.axes[3].deadzone, 30);
+        assert_eq!(
+            Setting::RampAccel.set(&mut config, 0),
+            Err(ShellError::OutOfRange)
+        );
+        // A center too close to an end is refused and leaves the config unchanged
+        assert_eq!(
+            Setting::Center(1).set(&mut config, 1900),
+            Err(ShellError::OutOfRange)
+        );
+        assert_eq!(config.calibration[1].center, 1500);
+        // Giving a wheel the motor of another one swaps them
+        assert_eq!(
+            Setting::WheelMotor(Wheel::RearLeft).set(&mut config, 1),
+            Ok(())
+        );
+        assert_eq!(config.wheels.motors[Wheel::FrontRight as usize], MotorId::C);
+        for setting in Setting::all() {
+            let value = setting.get(&config);
+            assert_eq!(setting.set(&mut config, value), Ok(()));
+        }
+        assert_eq!(
+            Setting::all().count(),
+            NUM_STICK_CHANNELS * 7 + NUM_CHANNEL_FUNCTIONS * 2 + 31
+        );
+    }
+}
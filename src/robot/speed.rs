@@ -0,0 +1,130 @@
+use crate::robot::calibration::ChannelCalibration;
+use crate::robot::flysky::channel_map::ChannelFunction;
+use crate::robot::flysky::{Switch, SwitchPosition};
+use crate::robot::helper::{map_value_to_potency, MAX_POTENCY};
+use crate::robot::ppm::PositionValue;
+
+/// Speed range selected with a switch.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gear {
+    Crawl,
+    Normal,
+    Sport,
+}
+
+impl Gear {
+    pub const ALL: [Gear; 3] = [Gear::Crawl, Gear::Normal, Gear::Sport];
+
+    /// Returns the gear of a switch: up crawl, middle normal and down sport. A two
+    /// position switch only has crawl and sport.
+    pub fn from_switch(switch: Switch) -> Self {
+        match switch.position() {
+            SwitchPosition::Low => Gear::Crawl,
+            SwitchPosition::Middle => Gear::Normal,
+            SwitchPosition::High => Gear::Sport,
+        }
+    }
+
+    /// Returns the name used by the serial shell and reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Gear::Crawl => "crawl",
+            Gear::Normal => "normal",
+            Gear::Sport => "sport",
+        }
+    }
+}
+
+/// Limits of the speed of the robot, for driving indoors or learning.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SpeedConfig {
+    /// Limits the speed with the VRA knob, from none turned left to full turned right.
+    pub knob: bool,
+    /// Switch selecting the gear, or none to drive at full speed.
+    pub gear_switch: Option<ChannelFunction>,
+    /// Speed of each gear in percent, indexed by `Gear`.
+    pub gear_percent: [u8; 3],
+}
+
+impl Default for SpeedConfig {
+    fn default() -> Self {
+        Self {
+            knob: false,
+            gear_switch: None,
+            gear_percent: [30, 60, 100],
+        }
+    }
+}
+
+impl SpeedConfig {
+    /// Returns the speed limit in percent in `gear`, if a switch selects it, with the
+    /// VRA knob at `knob`.
+    pub fn limit_percent(
+        &self,
+        gear: Option<Gear>,
+        knob: PositionValue,
+        calibration: &ChannelCalibration,
+    ) -> u8 {
+        let gear_percent = gear.map_or(100, |gear| self.gear_percent[gear as usize]) as u32;
+        let knob_potency = if self.knob {
+            map_value_to_potency(knob, calibration)
+        } else {
+            MAX_POTENCY
+        } as u32;
+        (gear_percent * knob_potency / MAX_POTENCY as u32) as u8
+    }
+
+    /// Returns the number of the gear switch in the shell and the saved settings,
+    /// 0 for none and 1 to 4 for SWA to SWD.
+    pub fn gear_switch_code(&self) -> u8 {
+        ChannelFunction::switch_code(self.gear_switch)
+    }
+
+    /// Sets the gear switch from its number. Returns false if it is not one.
+    pub fn set_gear_switch_code(&mut self, code: u8) -> bool {
+        match ChannelFunction::from_switch_code(code) {
+            Some(switch) => self.gear_switch = switch,
+            None => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOMINAL: ChannelCalibration = ChannelCalibration::NOMINAL;
+
+    #[test]
+    fn knob_and_gear_combine() {
+        let mut config = SpeedConfig::default();
+        assert_eq!(config.limit_percent(None, 1000, &NOMINAL), 100);
+        assert_eq!(config.limit_percent(Some(Gear::Crawl), 1000, &NOMINAL), 30);
+
+        config.knob = true;
+        assert_eq!(config.limit_percent(None, 1000, &NOMINAL), 0);
+        assert_eq!(config.limit_percent(None, 2000, &NOMINAL), 100);
+        assert_eq!(config.limit_percent(Some(Gear::Normal), 1500, &NOMINAL), 29);
+    }
+
+    #[test]
+    fn switches_select_gears() {
+        assert_eq!(
+            Gear::from_switch(Switch::ThreeWay(SwitchPosition::Middle)),
+            Gear::Normal
+        );
+        assert_eq!(
+            Gear::from_switch(Switch::TwoWay(SwitchPosition::High)),
+            Gear::Sport
+        );
+
+        let mut config = SpeedConfig::default();
+        for code in 0..=4 {
+            assert!(config.set_gear_switch_code(code));
+            assert_eq!(config.gear_switch_code(), code);
+        }
+        assert_eq!(config.gear_switch, Some(ChannelFunction::Swd));
+        assert!(!config.set_gear_switch_code(5));
+    }
+}
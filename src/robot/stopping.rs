@@ -0,0 +1,172 @@
+use crate::hal::{MotorId, StopMode};
+use crate::robot::flysky::channel_map::ChannelFunction;
+use crate::robot::flysky::{Switch, SwitchPosition};
+
+/// How the wheels stop when their duty reaches zero.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopStyle {
+    Brake,
+    Coast,
+    /// Brakes for `StopConfig::brake_ms` and then lets the wheel roll, for smooth
+    /// stops that still do not drift.
+    BrakeThenCoast,
+}
+
+impl StopStyle {
+    pub const ALL: [StopStyle; 3] = [
+        StopStyle::Brake,
+        StopStyle::Coast,
+        StopStyle::BrakeThenCoast,
+    ];
+
+    /// Returns the style chosen by a switch: up coast, middle brake then coast and
+    /// down brake. A two position switch only has coast and brake.
+    pub fn from_switch(switch: Switch) -> Self {
+        match switch.position() {
+            SwitchPosition::Low => StopStyle::Coast,
+            SwitchPosition::Middle => StopStyle::BrakeThenCoast,
+            SwitchPosition::High => StopStyle::Brake,
+        }
+    }
+
+    /// Returns the name used by the serial shell and reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            StopStyle::Brake => "brake",
+            StopStyle::Coast => "coast",
+            StopStyle::BrakeThenCoast => "brake then coast",
+        }
+    }
+
+    /// Returns the number of the style in the shell and the saved settings.
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the style with the given number, if any.
+    pub fn from_code(code: u8) -> Option<Self> {
+        StopStyle::ALL.get(code as usize).copied()
+    }
+}
+
+/// How the robot stops its wheels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StopConfig {
+    /// Style used when no switch selects one.
+    pub style: StopStyle,
+    /// Switch selecting the style, or none to always use `style`.
+    pub switch: Option<ChannelFunction>,
+    /// Time a wheel brakes before coasting, in the brake then coast style.
+    pub brake_ms: u16,
+}
+
+impl Default for StopConfig {
+    fn default() -> Self {
+        Self {
+            style: StopStyle::Brake,
+            switch: None,
+            brake_ms: 200,
+        }
+    }
+}
+
+impl StopConfig {
+    /// Returns the number of the style switch in the shell and the saved settings,
+    /// 0 for none and 1 to 4 for SWA to SWD.
+    pub fn switch_code(&self) -> u8 {
+        ChannelFunction::switch_code(self.switch)
+    }
+
+    /// Sets the style switch from its number. Returns false if it is not one.
+    pub fn set_switch_code(&mut self, code: u8) -> bool {
+        match ChannelFunction::from_switch_code(code) {
+            Some(switch) => self.switch = switch,
+            None => return false,
+        }
+        true
+    }
+}
+
+/// Time each motor started braking, indexed by `MotorId`, to release the brake in
+/// the brake then coast style.
+#[derive(Default)]
+pub struct StopTimer {
+    braking_since_ms: [Option<u32>; 4],
+}
+
+impl StopTimer {
+    /// Returns how `motor`, stopped at `now_ms`, must be stopped in `style`. A motor
+    /// brakes from the first call after it was driven.
+    pub fn stop_mode(
+        &mut self,
+        motor: MotorId,
+        style: StopStyle,
+        brake_ms: u16,
+        now_ms: u32,
+    ) -> StopMode {
+        let since_ms = *self.braking_since_ms[motor as usize].get_or_insert(now_ms);
+        match style {
+            StopStyle::Brake => StopMode::Brake,
+            StopStyle::Coast => StopMode::Coast,
+            StopStyle::BrakeThenCoast if now_ms.wrapping_sub(since_ms) < brake_ms as u32 => {
+                StopMode::Brake
+            }
+            StopStyle::BrakeThenCoast => StopMode::Coast,
+        }
+    }
+
+    /// Records that `motor` is driven again.
+    pub fn driven(&mut self, motor: MotorId) {
+        self.braking_since_ms[motor as usize] = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brakes_then_coasts() {
+        let mut timer = StopTimer::default();
+        let style = StopStyle::BrakeThenCoast;
+        let (a, b) = (MotorId::A, MotorId::B);
+        assert_eq!(timer.stop_mode(a, style, 200, 1000), StopMode::Brake);
+        assert_eq!(timer.stop_mode(a, style, 200, 1199), StopMode::Brake);
+        assert_eq!(timer.stop_mode(a, style, 200, 1200), StopMode::Coast);
+        // Another motor, or the same one driven again, brakes from its own stop
+        assert_eq!(timer.stop_mode(b, style, 200, 1200), StopMode::Brake);
+        timer.driven(a);
+        assert_eq!(timer.stop_mode(a, style, 200, 1300), StopMode::Brake);
+
+        assert_eq!(
+            timer.stop_mode(a, StopStyle::Coast, 200, 1300),
+            StopMode::Coast
+        );
+        assert_eq!(
+            timer.stop_mode(a, StopStyle::Brake, 200, 9000),
+            StopMode::Brake
+        );
+    }
+
+    #[test]
+    fn switches_select_styles() {
+        assert_eq!(
+            StopStyle::from_switch(Switch::ThreeWay(SwitchPosition::Middle)),
+            StopStyle::BrakeThenCoast
+        );
+        assert_eq!(
+            StopStyle::from_switch(Switch::TwoWay(SwitchPosition::Low)),
+            StopStyle::Coast
+        );
+        for code in 0..=2 {
+            assert_eq!(StopStyle::from_code(code).map(StopStyle::code), Some(code));
+        }
+        assert_eq!(StopStyle::from_code(3), None);
+
+        let mut config = StopConfig::default();
+        assert!(config.set_switch_code(1));
+        assert_eq!(config.switch, Some(ChannelFunction::Swa));
+        assert_eq!(config.switch_code(), 1);
+        assert!(!config.set_switch_code(5));
+    }
+}
@@ -0,0 +1,472 @@
+use crate::codec::{Reader, Writer};
+use crate::crc::crc16;
+use crate::hal::{ConfigStorage, MotorId, NUM_STICK_CHANNELS};
+use crate::robot::arming::ArmMode;
+use crate::robot::config::Config;
+use crate::robot::flysky::channel_map::NUM_CHANNEL_FUNCTIONS;
+use crate::robot::kinematics::DriveMode;
+use crate::robot::shell::Setting;
+use crate::robot::stopping::StopStyle;
+
+// Record layout at the start of the storage, multi-byte fields in little endian:
+// magic ("OX") | version | payload length | payload | CRC-16
+// The CRC-16/CCITT-FALSE covers from the version to the end of the payload.
+// The payload only grows: each version appends its new fields to the previous
+// layout, so older records load with defaults for what they lack.
+const MAGIC: [u8; 2] = *b"OX";
+pub const CONFIG_VERSION: u8 = 11;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const CRC_LEN: usize = 2;
+// Payload length written by each version, indexed by version - 1
+const PAYLOAD_LEN: [usize; CONFIG_VERSION as usize] = [
+    NUM_STICK_CHANNELS * 5 + 14,
+    // Stick calibration
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6,
+    // Channel map
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_STICK_CHANNELS * 2,
+    // Switches in the channel map
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2,
+    // Drive mode
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 1,
+    // Speed knob and gears
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 6,
+    // Stop style
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 10,
+    // Motor of each wheel
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 14,
+    // Minimum duty of each motor
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 18,
+    // Arm switch from the channel map
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 18,
+    // Stick center band, failsafe timeout and control rate
+    NUM_STICK_CHANNELS * 5 + 14 + NUM_STICK_CHANNELS * 6 + NUM_CHANNEL_FUNCTIONS * 2 + 23,
+];
+const CURRENT_PAYLOAD_LEN: usize = PAYLOAD_LEN[CONFIG_VERSION as usize - 1];
+pub const RECORD_LEN: usize = HEADER_LEN + CURRENT_PAYLOAD_LEN + CRC_LEN;
+// Value of the bytes of an erased EEPROM
+const ERASED: u8 = 0xFF;
+
+// Encoding of `ArmMode`, followed by the switch code. Before version 10 a switch
+// was a stick channel instead, and such records arm with the gesture.
+const ARM_MODE_GESTURE: u8 = 0;
+const ARM_MODE_SWITCH: u8 = 1;
+
+/// Reasons the stored settings are not loaded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoadError {
+    /// Nothing was ever saved.
+    Blank,
+    BadMagic,
+    /// Saved by a newer firmware.
+    UnsupportedVersion(u8),
+    BadLength(u8),
+    BadChecksum,
+    /// A value is out of the range the shell accepts.
+    InvalidValue,
+}
+
+impl LoadError {
+    /// Returns a short description for the serial reports.
+    pub fn message(self) -> &'static str {
+        match self {
+            LoadError::Blank => "none saved",
+            LoadError::BadMagic => "not a settings record",
+            LoadError::UnsupportedVersion(_) => "unsupported version",
+            LoadError::BadLength(_) => "bad length",
+            LoadError::BadChecksum => "bad checksum",
+            LoadError::InvalidValue => "invalid value",
+        }
+    }
+}
+
+/// Reads the settings saved in `storage`.
+pub fn load<E: ConfigStorage>(storage: &mut E) -> Result<Config, LoadError> {
+    let mut record = [0; RECORD_LEN];
+    storage.read(&mut record);
+    decode(&record)
+}
+
+/// Saves `config` in `storage` and reads it back. Returns false if it does not
+/// read back the same.
+pub fn save<E: ConfigStorage>(storage: &mut E, config: &Config) -> bool {
+    storage.write(&encode(config));
+    load(storage) == Ok(*config)
+}
+
+/// Returns the record holding `config` in the current layout.
+pub fn encode(config: &Config) -> [u8; RECORD_LEN] {
+    let mut record = [0; RECORD_LEN];
+    record[..MAGIC.len()].copy_from_slice(&MAGIC);
+    record[2] = CONFIG_VERSION;
+    record[3] = CURRENT_PAYLOAD_LEN as u8;
+
+    let mut payload = Writer::new(&mut record[HEADER_LEN..HEADER_LEN + CURRENT_PAYLOAD_LEN]);
+    // Version 1
+    for axis in config.axes {
+        payload.u16(axis.deadzone);
+        payload.u8(axis.expo);
+        payload.u8(axis.rate);
+        payload.bool(axis.reverse);
+    }
+    payload.u8(config.ramp.accel);
+    payload.u8(config.ramp.decel);
+    payload.u8(match config.arming {
+        ArmMode::Gesture => ARM_MODE_GESTURE,
+        ArmMode::Switch(_) => ARM_MODE_SWITCH,
+    });
+    payload.u8(config.arming.switch_code());
+    payload.u16(config.battery.warning_mv);
+    payload.u16(config.battery.cutoff_mv);
+    payload.u8(config.battery.min_power);
+    payload.u8(config.telemetry_rate_hz);
+    // Written per motor before the wheel map, which then always was A to D
+    for invert in config.wheels.invert {
+        payload.bool(invert);
+    }
+    // Version 2
+    for calibration in config.calibration {
+        payload.u16(calibration.min);
+        payload.u16(calibration.center);
+        payload.u16(calibration.max);
+    }
+    // Version 3 holds the sticks of the channel map, version 4 the switches
+    for source in config.channel_map.sources {
+        payload.u8(source.channel);
+        payload.bool(source.reverse);
+    }
+    // Version 5
+    payload.u8(config.drive.code());
+    // Version 6
+    payload.bool(config.speed.knob);
+    payload.u8(config.speed.gear_switch_code());
+    for percent in config.speed.gear_percent {
+        payload.u8(percent);
+    }
+    // Version 7
+    payload.u8(config.stop.style.code());
+    payload.u8(config.stop.switch_code());
+    payload.u16(config.stop.brake_ms);
+    // Version 8
+    for motor in config.wheels.motors {
+        payload.u8(motor as u8);
+    }
+    // Version 9
+    for min_duty in config.motor_min_duty {
+        payload.u8(min_duty);
+    }
+    // Version 11
+    payload.u16(config.center_band);
+    payload.u16(config.failsafe_timeout_ms);
+    payload.u8(config.control_rate_hz);
+
+    let crc = crc16(&record[MAGIC.len()..HEADER_LEN + CURRENT_PAYLOAD_LEN]);
+    record[RECORD_LEN - CRC_LEN..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Decodes a record of any known version, migrating it to the current `Config`.
+pub fn decode(record: &[u8]) -> Result<Config, LoadError> {
+    if record.len() < HEADER_LEN {
+        return Err(LoadError::BadLength(0));
+    }
+    if record[..HEADER_LEN].iter().all(|byte| *byte == ERASED) {
+        return Err(LoadError::Blank);
+    }
+    if record[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let version = record[2];
+    let payload_len = match version {
+        1..=CONFIG_VERSION => PAYLOAD_LEN[version as usize - 1],
+        _ => return Err(LoadError::UnsupportedVersion(version)),
+    };
+    let crc_start = HEADER_LEN + payload_len;
+    if record[3] as usize != payload_len || record.len() < crc_start + CRC_LEN {
+        return Err(LoadError::BadLength(record[3]));
+    }
+    let crc = u16::from_le_bytes([record[crc_start], record[crc_start + 1]]);
+    if crc != crc16(&record[MAGIC.len()..crc_start]) {
+        return Err(LoadError::BadChecksum);
+    }
+
+    let mut payload = Reader::new(&record[HEADER_LEN..crc_start]);
+    let mut config = Config::default();
+    // Version 1
+    for axis in config.axes.iter_mut() {
+        axis.deadzone = payload.u16();
+        axis.expo = payload.u8();
+        axis.rate = payload.u8();
+        axis.reverse = payload.bool();
+    }
+    config.ramp.accel = payload.u8();
+    config.ramp.decel = payload.u8();
+    let (mode, switch) = (payload.u8(), payload.u8());
+    config.arming = match mode {
+        ARM_MODE_GESTURE => ArmMode::Gesture,
+        ARM_MODE_SWITCH if version < 10 => ArmMode::Gesture,
+        ARM_MODE_SWITCH => match ArmMode::from_switch_code(switch) {
+            Some(ArmMode::Switch(function)) => ArmMode::Switch(function),
+            _ => return Err(LoadError::InvalidValue),
+        },
+        _ => return Err(LoadError::InvalidValue),
+    };
+    config.battery.warning_mv = payload.u16();
+    config.battery.cutoff_mv = payload.u16();
+    config.battery.min_power = payload.u8();
+    config.telemetry_rate_hz = payload.u8();
+    for invert in config.wheels.invert.iter_mut() {
+        *invert = payload.bool();
+    }
+    if version >= 2 {
+        for calibration in config.calibration.iter_mut() {
+            calibration.min = payload.u16();
+            calibration.center = payload.u16();
+            calibration.max = payload.u16();
+        }
+    }
+    let map_len = match version {
+        1 | 2 => 0,
+        3 => NUM_STICK_CHANNELS,
+        _ => NUM_CHANNEL_FUNCTIONS,
+    };
+    for source in config.channel_map.sources[..map_len].iter_mut() {
+        source.channel = payload.u8();
+        source.reverse = payload.bool();
+    }
+    if version >= 5 {
+        config.drive = DriveMode::from_code(payload.u8()).ok_or(LoadError::InvalidValue)?;
+    }
+    if version >= 6 {
+        config.speed.knob = payload.bool();
+        if !config.speed.set_gear_switch_code(payload.u8()) {
+            return Err(LoadError::InvalidValue);
+        }
+        for percent in config.speed.gear_percent.iter_mut() {
+            *percent = payload.u8();
+        }
+    }
+    if version >= 7 {
+        config.stop.style = StopStyle::from_code(payload.u8()).ok_or(LoadError::InvalidValue)?;
+        if !config.stop.set_switch_code(payload.u8()) {
+            return Err(LoadError::InvalidValue);
+        }
+        config.stop.brake_ms = payload.u16();
+    }
+    if version >= 8 {
+        for motor in config.wheels.motors.iter_mut() {
+            *motor = *MotorId::ALL
+                .get(payload.u8() as usize)
+                .ok_or(LoadError::InvalidValue)?;
+        }
+    }
+    if version >= 9 {
+        for min_duty in config.motor_min_duty.iter_mut() {
+            *min_duty = payload.u8();
+        }
+    }
+    if version >= 11 {
+        config.center_band = payload.u16();
+        config.failsafe_timeout_ms = payload.u16();
+        config.control_rate_hz = payload.u8();
+    }
+
+    if !Setting::all().all(|setting| setting.is_valid(&config)) {
+        return Err(LoadError::InvalidValue);
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockStorage;
+    use crate::robot::flysky::channel_map::ChannelFunction;
+    use crate::robot::kinematics::Kinematics;
+    use crate::robot::speed::Gear;
+    use crate::robot::wheels::{Wheel, WheelMap};
+
+    fn tuned() -> Config {
+        let mut config = Config {
+            arming: ArmMode::Switch(ChannelFunction::Swa),
+            telemetry_rate_hz: 0,
+            ..Config::default()
+        };
+        config.axes[1].expo = 40;
+        config.axes[3].reverse = true;
+        config.ramp.decel = 255;
+        config.battery.cutoff_mv = 5800;
+        config.wheels.invert[2] = true;
+        config.wheels.assign(Wheel::FrontLeft, MotorId::B);
+        config.calibration[0].center = 1516;
+        config.channel_map.sources[1].channel = 2;
+        config.channel_map.sources[2].channel = 1;
+        config.channel_map.sources[8].reverse = true;
+        config.drive = DriveMode::Fixed(Kinematics::Tank);
+        config.speed.knob = true;
+        config.speed.gear_switch = Some(ChannelFunction::Swc);
+        config.speed.gear_percent[Gear::Crawl as usize] = 15;
+        config.stop.style = StopStyle::BrakeThenCoast;
+        config.stop.switch = Some(ChannelFunction::Swb);
+        config.stop.brake_ms = 350;
+        config.motor_min_duty = [70, 75, 0, 64];
+        config.center_band = 30;
+        config.failsafe_timeout_ms = 400;
+        config.control_rate_hz = 100;
+        config
+    }
+
+    /// Returns the record an older firmware saved for `config`, that is the current
+    /// record cut to the payload of `version` with its header and CRC fixed.
+    fn encode_version(config: &Config, version: u8) -> std::vec::Vec<u8> {
+        let payload_len = PAYLOAD_LEN[version as usize - 1];
+        let mut record = encode(config)[..HEADER_LEN + payload_len].to_vec();
+        record[2] = version;
+        record[3] = payload_len as u8;
+        let crc = crc16(&record[MAGIC.len()..]);
+        record.extend_from_slice(&crc.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let mut storage = MockStorage::default();
+        assert_eq!(load(&mut storage), Err(LoadError::Blank));
+        assert!(save(&mut storage, &tuned()));
+        assert_eq!(load(&mut storage), Ok(tuned()));
+    }
+
+    #[test]
+    fn migrates_older_records() {
+        // Version 1 had no stick calibration, no channel map, no drive mode, no
+        // speed limits, no stop style, no wheel map and no min duty. Its motor
+        // inversions are those of the wheels, with motors A to D from front left to
+        // rear right. Until version 10 the arm switch was a stick channel, which
+        // falls back to the gesture.
+        //
+        // Until version 11 the center band, failsafe timeout and control rate were
+        // fixed at their defaults.
+        let tuned_v10 = Config {
+            center_band: Config::default().center_band,
+            failsafe_timeout_ms: Config::default().failsafe_timeout_ms,
+            control_rate_hz: Config::default().control_rate_hz,
+            ..tuned()
+        };
+        let old_wheels = WheelMap {
+            motors: MotorId::ALL,
+            ..tuned().wheels
+        };
+        let record = encode_version(&tuned(), 1);
+        let expected = Config {
+            arming: ArmMode::Gesture,
+            calibration: Config::default().calibration,
+            channel_map: Config::default().channel_map,
+            drive: Config::default().drive,
+            speed: Config::default().speed,
+            stop: Config::default().stop,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&record), Ok(expected));
+
+        let expected_v2 = Config {
+            arming: ArmMode::Gesture,
+            channel_map: Config::default().channel_map,
+            drive: Config::default().drive,
+            speed: Config::default().speed,
+            stop: Config::default().stop,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 2)), Ok(expected_v2));
+
+        // Version 3 had no switches in the channel map
+        let mut expected_v3 = tuned_v10;
+        expected_v3.channel_map.sources[8].reverse = false;
+        expected_v3.arming = ArmMode::Gesture;
+        expected_v3.drive = Config::default().drive;
+        expected_v3.speed = Config::default().speed;
+        expected_v3.stop = Config::default().stop;
+        expected_v3.wheels = old_wheels;
+        expected_v3.motor_min_duty = [0; 4];
+        assert_eq!(decode(&encode_version(&tuned(), 3)), Ok(expected_v3));
+
+        let expected_v4 = Config {
+            arming: ArmMode::Gesture,
+            drive: Config::default().drive,
+            speed: Config::default().speed,
+            stop: Config::default().stop,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 4)), Ok(expected_v4));
+
+        let expected_v5 = Config {
+            arming: ArmMode::Gesture,
+            speed: Config::default().speed,
+            stop: Config::default().stop,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 5)), Ok(expected_v5));
+
+        let expected_v6 = Config {
+            arming: ArmMode::Gesture,
+            stop: Config::default().stop,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 6)), Ok(expected_v6));
+
+        let expected_v7 = Config {
+            arming: ArmMode::Gesture,
+            wheels: old_wheels,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 7)), Ok(expected_v7));
+
+        let expected_v8 = Config {
+            arming: ArmMode::Gesture,
+            motor_min_duty: [0; 4],
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 8)), Ok(expected_v8));
+
+        let expected_v9 = Config {
+            arming: ArmMode::Gesture,
+            ..tuned_v10
+        };
+        assert_eq!(decode(&encode_version(&tuned(), 9)), Ok(expected_v9));
+        assert_eq!(decode(&encode_version(&tuned(), 10)), Ok(tuned_v10));
+
+        let mut storage = MockStorage::default();
+        storage.fill(&record);
+        assert_eq!(load(&mut storage), Ok(expected));
+    }
+
+    #[test]
+    fn rejects_corrupted_records() {
+        let record = encode(&tuned());
+
+        let mut corrupted = record;
+        corrupted[10] ^= 0x01;
+        assert_eq!(decode(&corrupted), Err(LoadError::BadChecksum));
+
+        let mut newer = record;
+        newer[2] = CONFIG_VERSION + 1;
+        assert_eq!(
+            decode(&newer),
+            Err(LoadError::UnsupportedVersion(CONFIG_VERSION + 1))
+        );
+
+        // A valid record holding a value the shell would refuse
+        let mut config = tuned();
+        config.axes[0].rate = 101;
+        assert_eq!(decode(&encode(&config)), Err(LoadError::InvalidValue));
+    }
+}
@@ -0,0 +1,52 @@
+/// Timing statistics of the fixed rate control loop.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LoopStats {
+    /// Control steps run since start.
+    pub steps: u32,
+    /// Ticks missed because a step took longer than the loop period.
+    pub overruns: u32,
+    /// Duration of the last step in microseconds.
+    pub last_step_us: u32,
+    /// Longest step in microseconds since the last `reset_max`.
+    pub max_step_us: u32,
+}
+
+impl LoopStats {
+    /// Records a step that ran `step_us` microseconds, started `elapsed_ticks`
+    /// ticks after the previous one.
+    pub fn record(&mut self, elapsed_ticks: u16, step_us: u32) {
+        self.steps = self.steps.wrapping_add(1);
+        self.overruns = self
+            .overruns
+            .saturating_add(elapsed_ticks.saturating_sub(1) as u32);
+        self.last_step_us = step_us;
+        self.max_step_us = self.max_step_us.max(step_us);
+    }
+
+    /// Starts a new window for the longest step.
+    pub fn reset_max(&mut self) {
+        self.max_step_us = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_missed_ticks_as_overruns() {
+        let mut stats = LoopStats::default();
+        stats.record(1, 3000);
+        stats.record(3, 45000);
+        stats.record(1, 2000);
+
+        assert_eq!(stats.steps, 3);
+        assert_eq!(stats.overruns, 2);
+        assert_eq!(stats.last_step_us, 2000);
+        assert_eq!(stats.max_step_us, 45000);
+
+        stats.reset_max();
+        stats.record(1, 2500);
+        assert_eq!(stats.max_step_us, 2500);
+    }
+}
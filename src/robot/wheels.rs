@@ -0,0 +1,115 @@
+use crate::hal::MotorId;
+use crate::robot::mecanum::WheelOutputs;
+
+/// Position of a wheel on the chassis.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wheel {
+    FrontLeft,
+    FrontRight,
+    RearLeft,
+    RearRight,
+}
+
+impl Wheel {
+    pub const ALL: [Wheel; 4] = [
+        Wheel::FrontLeft,
+        Wheel::FrontRight,
+        Wheel::RearLeft,
+        Wheel::RearRight,
+    ];
+
+    /// Returns the short name used by the serial shell.
+    pub fn name(self) -> &'static str {
+        match self {
+            Wheel::FrontLeft => "fl",
+            Wheel::FrontRight => "fr",
+            Wheel::RearLeft => "rl",
+            Wheel::RearRight => "rr",
+        }
+    }
+
+    /// Returns the name reported by the wheel test.
+    pub fn description(self) -> &'static str {
+        match self {
+            Wheel::FrontLeft => "front left",
+            Wheel::FrontRight => "front right",
+            Wheel::RearLeft => "rear left",
+            Wheel::RearRight => "rear right",
+        }
+    }
+
+    /// Returns the duty of the wheel in `outputs`.
+    pub fn duty(self, outputs: WheelOutputs) -> i16 {
+        match self {
+            Wheel::FrontLeft => outputs.a,
+            Wheel::FrontRight => outputs.b,
+            Wheel::RearLeft => outputs.c,
+            Wheel::RearRight => outputs.d,
+        }
+    }
+}
+
+/// Motor driving each wheel and whether it is wired backwards, indexed by `Wheel`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WheelMap {
+    pub motors: [MotorId; 4],
+    pub invert: [bool; 4],
+}
+
+impl Default for WheelMap {
+    /// Motor A front left, B front right, C rear left and D rear right.
+    fn default() -> Self {
+        Self {
+            motors: MotorId::ALL,
+            invert: [false; 4],
+        }
+    }
+}
+
+impl WheelMap {
+    /// Returns the motor of `wheel` and the duty that turns the wheel at `duty`.
+    pub fn route(&self, wheel: Wheel, duty: i16) -> (MotorId, i16) {
+        let duty = if self.invert[wheel as usize] {
+            -duty
+        } else {
+            duty
+        };
+        (self.motors[wheel as usize], duty)
+    }
+
+    /// Makes `motor` drive `wheel`. The wheel that had it takes the motor of
+    /// `wheel`, so each motor keeps driving a single wheel.
+    pub fn assign(&mut self, wheel: Wheel, motor: MotorId) {
+        if let Some(other) = self.motors.iter().position(|id| *id == motor) {
+            self.motors[other] = self.motors[wheel as usize];
+        }
+        self.motors[wheel as usize] = motor;
+    }
+
+    /// Returns true if every motor drives exactly one wheel.
+    pub fn is_valid(&self) -> bool {
+        MotorId::ALL
+            .iter()
+            .all(|motor| self.motors.iter().filter(|id| *id == motor).count() == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assigning_a_motor_swaps_wheels() {
+        let mut map = WheelMap::default();
+        map.assign(Wheel::FrontLeft, MotorId::D);
+        assert_eq!(map.motors, [MotorId::D, MotorId::B, MotorId::C, MotorId::A]);
+        assert!(map.is_valid());
+
+        map.invert[Wheel::RearRight as usize] = true;
+        assert_eq!(map.route(Wheel::RearRight, 120), (MotorId::A, -120));
+        assert_eq!(map.route(Wheel::FrontLeft, 120), (MotorId::D, 120));
+
+        map.motors[1] = MotorId::C;
+        assert!(!map.is_valid());
+    }
+}
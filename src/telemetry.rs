@@ -0,0 +1,191 @@
+use crate::codec::{Reader, Writer};
+use crate::crc::crc16;
+use crate::hal::{FrameStats, NUM_STICK_CHANNELS};
+
+// Frame layout, multi-byte fields in little endian:
+// sync (0xA5 0x5A) | version | message id | payload length | payload | CRC-16
+// The CRC-16/CCITT-FALSE covers from the version to the end of the payload.
+pub const SYNC: [u8; 2] = [0xA5, 0x5A];
+pub const PROTOCOL_VERSION: u8 = 2;
+pub const TELEMETRY_MESSAGE_ID: u8 = 0x01;
+const HEADER_LEN: usize = SYNC.len() + 3;
+const CRC_LEN: usize = 2;
+pub const TELEMETRY_PAYLOAD_LEN: usize = 51;
+pub const TELEMETRY_FRAME_LEN: usize = HEADER_LEN + TELEMETRY_PAYLOAD_LEN + CRC_LEN;
+
+// Bits of `Telemetry::flags`
+pub const FLAG_ARMED: u8 = 0x01;
+pub const FLAG_FAILSAFE: u8 = 0x02;
+pub const FLAG_BATTERY_LOW: u8 = 0x04;
+pub const FLAG_BATTERY_CUTOFF: u8 = 0x08;
+
+/// Snapshot of the robot sent at the telemetry rate.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Telemetry {
+    /// Milliseconds since start.
+    pub time_ms: u32,
+    /// Channel values in microseconds, in the order of the channel map.
+    pub channels: [u16; NUM_STICK_CHANNELS],
+    /// Good, bad and missed frames of the receiver since start.
+    pub frames: FrameStats,
+    /// Shaped lateral, forward and rotation inputs of the mixer, -255..=255.
+    pub vx: i16,
+    pub vy: i16,
+    pub omega: i16,
+    /// Signed duty applied to wheels A, B, C and D.
+    pub wheels: [i16; 4],
+    /// Filtered battery voltage.
+    pub battery_mv: u16,
+    /// Duration of the last and the longest control step, saturated at 65535.
+    pub last_step_us: u16,
+    pub max_step_us: u16,
+    /// Control ticks missed since start, saturated at 65535.
+    pub overruns: u16,
+    /// Combination of the `FLAG_*` bits.
+    pub flags: u8,
+}
+
+/// Reasons a frame is rejected by `Telemetry::decode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    Truncated,
+    BadSync,
+    UnsupportedVersion(u8),
+    UnknownMessage(u8),
+    BadLength(u8),
+    BadChecksum,
+}
+
+impl Telemetry {
+    /// Returns the complete frame carrying this snapshot.
+    pub fn encode(&self) -> [u8; TELEMETRY_FRAME_LEN] {
+        let mut frame = [0; TELEMETRY_FRAME_LEN];
+        frame[..SYNC.len()].copy_from_slice(&SYNC);
+        frame[2] = PROTOCOL_VERSION;
+        frame[3] = TELEMETRY_MESSAGE_ID;
+        frame[4] = TELEMETRY_PAYLOAD_LEN as u8;
+
+        let mut payload = Writer::new(&mut frame[HEADER_LEN..HEADER_LEN + TELEMETRY_PAYLOAD_LEN]);
+        payload.u32(self.time_ms);
+        for channel in self.channels {
+            payload.u16(channel);
+        }
+        payload.u32(self.frames.good);
+        payload.u32(self.frames.bad);
+        payload.u32(self.frames.missed);
+        payload.i16(self.vx);
+        payload.i16(self.vy);
+        payload.i16(self.omega);
+        for wheel in self.wheels {
+            payload.i16(wheel);
+        }
+        payload.u16(self.battery_mv);
+        payload.u16(self.last_step_us);
+        payload.u16(self.max_step_us);
+        payload.u16(self.overruns);
+        payload.u8(self.flags);
+
+        let crc = crc16(&frame[SYNC.len()..HEADER_LEN + TELEMETRY_PAYLOAD_LEN]);
+        frame[TELEMETRY_FRAME_LEN - CRC_LEN..].copy_from_slice(&crc.to_le_bytes());
+        frame
+    }
+
+    /// Decodes a frame starting at the first sync byte.
+    pub fn decode(frame: &[u8]) -> Result<Self, DecodeError> {
+        if frame.len() < TELEMETRY_FRAME_LEN {
+            return Err(DecodeError::Truncated);
+        }
+        if frame[..SYNC.len()] != SYNC {
+            return Err(DecodeError::BadSync);
+        }
+        if frame[2] != PROTOCOL_VERSION {
+            return Err(DecodeError::UnsupportedVersion(frame[2]));
+        }
+        if frame[3] != TELEMETRY_MESSAGE_ID {
+            return Err(DecodeError::UnknownMessage(frame[3]));
+        }
+        if frame[4] as usize != TELEMETRY_PAYLOAD_LEN {
+            return Err(DecodeError::BadLength(frame[4]));
+        }
+        let crc_start = HEADER_LEN + TELEMETRY_PAYLOAD_LEN;
+        let crc = u16::from_le_bytes([frame[crc_start], frame[crc_start + 1]]);
+        if crc != crc16(&frame[SYNC.len()..crc_start]) {
+            return Err(DecodeError::BadChecksum);
+        }
+
+        let mut payload = Reader::new(&frame[HEADER_LEN..crc_start]);
+        let mut telemetry = Telemetry {
+            time_ms: payload.u32(),
+            ..Telemetry::default()
+        };
+        for channel in telemetry.channels.iter_mut() {
+            *channel = payload.u16();
+        }
+        telemetry.frames.good = payload.u32();
+        telemetry.frames.bad = payload.u32();
+        telemetry.frames.missed = payload.u32();
+        telemetry.vx = payload.i16();
+        telemetry.vy = payload.i16();
+        telemetry.omega = payload.i16();
+        for wheel in telemetry.wheels.iter_mut() {
+            *wheel = payload.i16();
+        }
+        telemetry.battery_mv = payload.u16();
+        telemetry.last_step_us = payload.u16();
+        telemetry.max_step_us = payload.u16();
+        telemetry.overruns = payload.u16();
+        telemetry.flags = payload.u8();
+        Ok(telemetry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Telemetry {
+        Telemetry {
+            time_ms: 123_456,
+            channels: [1500, 2000, 1000, 1496, 1500, 1000],
+            frames: FrameStats {
+                good: 70_000,
+                bad: 12,
+                missed: 3,
+            },
+            vx: -12,
+            vy: 255,
+            omega: 7,
+            wheels: [250, -3, 0, -255],
+            battery_mv: 7150,
+            last_step_us: 1800,
+            max_step_us: 2400,
+            overruns: 1,
+            flags: FLAG_ARMED | FLAG_BATTERY_LOW,
+        }
+    }
+
+    #[test]
+    fn decodes_what_it_encodes() {
+        let frame = sample().encode();
+        assert_eq!(frame[..5], [0xA5, 0x5A, PROTOCOL_VERSION, 0x01, 51]);
+        assert_eq!(Telemetry::decode(&frame), Ok(sample()));
+    }
+
+    #[test]
+    fn rejects_corrupted_frames() {
+        let frame = sample().encode();
+
+        let mut corrupted = frame;
+        corrupted[20] ^= 0x10;
+        assert_eq!(Telemetry::decode(&corrupted), Err(DecodeError::BadChecksum));
+
+        let mut newer = frame;
+        newer[2] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            Telemetry::decode(&newer),
+            Err(DecodeError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        assert_eq!(Telemetry::decode(&frame[1..]), Err(DecodeError::Truncated));
+    }
+}